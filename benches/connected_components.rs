@@ -0,0 +1,134 @@
+// cargo bench --bench connected_components
+// times labeling 240x135 masks against the flood fill it replaced
+use std::{collections::VecDeque, time::{Duration, Instant}};
+
+use bumper_robot::{img::{Image, L8}, img_connected_components::{Connectivity, connected_components}};
+
+
+const WIDTH: usize = 240;
+const HEIGHT: usize = 135;
+const RUNS: u32 = 200;
+
+// deterministic noise without a dependency
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> usize {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 33) as usize
+    }
+}
+
+// count squares of 1 to 4 pixels, which may overlap
+fn blobs(count: usize) -> Image<L8> {
+    let mut rng = Lcg(count as u64);
+    let mut mask: Image<L8> = Image::new(WIDTH, HEIGHT);
+
+    for _ in 0..count {
+        let size = 1 + rng.next() % 4;
+        let (x, y) = (rng.next() % (WIDTH - size), rng.next() % (HEIGHT - size));
+
+        for y in y..y + size {
+            for x in x..x + size {
+                mask.data_mut()[y * WIDTH + x] = u8::MAX;
+            }
+        }
+    }
+    mask
+}
+
+fn time(mut f: impl FnMut()) -> Duration {
+    f();
+
+    let start = Instant::now();
+    for _ in 0..RUNS { f() }
+    start.elapsed() / RUNS
+}
+
+fn main() {
+    // the flood fill runs out of u8 ids after 253 components
+    for &count in &[20, 150, 2000] {
+        let mask = blobs(count);
+
+        let (_, components) = connected_components(&mask, Connectivity::Four, None);
+
+        let four = time(|| { connected_components(&mask, Connectivity::Four, None); });
+        let eight = time(|| { connected_components(&mask, Connectivity::Eight, None); });
+
+        print!("{:>4} blobs, {:>4} components: two pass 4-connected {:>8.1?}, 8-connected {:>8.1?}",
+            count, components.len(), four, eight);
+
+        if components.len() < 250 {
+            let flood = time(|| { flood_fill::connected_components(&mut mask.data().to_vec(), WIDTH, HEIGHT); });
+
+            print!(", flood fill {:>8.1?}", flood);
+        }
+        println!();
+    }
+}
+
+// the labeling before the two pass version, without the unused bounding boxes
+mod flood_fill {
+    use super::*;
+
+    const UNCOLORED_BACKGROUND: u8 = u8::MIN;
+    const COLORED_BACKGROUND: u8 = 1;
+    const UNCOLORED_REGION: u8 = u8::MAX;
+
+    fn neighbors(img: &[u8], width: usize, height: usize, x: usize, y: usize) -> Vec<(usize, usize)> {
+        let idx = y * width + x;
+        let uncolored = |v: u8| v == UNCOLORED_REGION || v == UNCOLORED_BACKGROUND;
+
+        let mut r = vec![];
+
+        if x > 0 && uncolored(img[idx - 1]) { r.push((x - 1, y)) }
+        if x < width - 1 && uncolored(img[idx + 1]) { r.push((x + 1, y)) }
+        if y > 0 && uncolored(img[idx - width]) { r.push((x, y - 1)) }
+        if y < height - 1 && uncolored(img[idx + width]) { r.push((x, y + 1)) }
+        r
+    }
+
+    fn dfs_cc(img: &mut [u8], q: &mut VecDeque<(usize, usize)>, width: usize, height: usize, id: u8, x: usize, y: usize) -> usize {
+        let mut stack = vec![(x, y)];
+        let mut area = 0;
+
+        while let Some((x, y)) = stack.pop() {
+            let idx = y * width + x;
+
+            match img[idx] {
+                UNCOLORED_REGION => {
+                    img[idx] = id;
+                    area += 1;
+                    stack.extend(neighbors(img, width, height, x, y));
+                },
+                UNCOLORED_BACKGROUND => q.push_back((x, y)),
+                _ => {},
+            }
+        }
+        area
+    }
+
+    // areas of the components
+    pub fn connected_components(img: &mut [u8], width: usize, height: usize) -> Vec<usize> {
+        let mut id = COLORED_BACKGROUND;
+        let mut q = VecDeque::from(vec![(0, 0)]);
+        let mut components = vec![];
+
+        while let Some((x, y)) = q.pop_front() {
+            let idx = y * width + x;
+
+            match img[idx] {
+                UNCOLORED_REGION => {
+                    id += 1;
+                    components.push(dfs_cc(img, &mut q, width, height, id, x, y));
+                },
+                UNCOLORED_BACKGROUND => {
+                    img[idx] = COLORED_BACKGROUND;
+                    q.extend(neighbors(img, width, height, x, y));
+                },
+                _ => {},
+            }
+        }
+        components
+    }
+}
@@ -1,44 +1,380 @@
-use crate::img::{self, SaveBuffer};
+use serde::Deserialize;
 
+use crate::img::{self, Image, L8, Rgb8};
+
+
+pub const BACKGROUND_DIRECTORY: &str = "./data";
+pub const BACKGROUND_FINAL: &str = "./data/background_final.png";
+pub const BACKGROUND_FLICKER: &str = "./data/background_flicker.png";
+
+
+// How frame pixels are compared against the background
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Subtraction {
+    // background when all channels are equal
+    // remove also zeroes the equal channels of foreground pixels
+    Exact,
+    // background when every channel is within the tolerance
+    Tolerance([u8; 3]),
+    // background when the squared Mahalanobis distance to the sample mean is at most threshold^2
+    // channels are treated as independent, variances are raised to at least min_variance
+    Statistical { threshold: f32, min_variance: f32 },
+}
+
+// How samples are combined into one background image
+// both keep the channels of a pixel together
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Estimator {
+    // most frequent color
+    Mode,
+    // sample color of median brightness
+    Median,
+}
 
 // The background of the active area in RGB
-// - check for a background_final file
-// - check for background<i> sample files and generate
-// - fail
-// TODO: automate the generation of sample files (different startup / mode change)
-pub fn fetch_background() -> Option<Vec<u8>> {
-    const BACKGROUND_PREFIX: &str = "./data/background";
-    const BACKGROUND_FINAL_SUFFIX: &str = "_final";
-    const EXTENSION: &str = ".png";
+#[derive(Clone)]
+pub struct Background {
+    pub image: Image<Rgb8>,
+    // per pixel and channel, interleaved like image
+    // the image itself with zero variance when there are no samples
+    pub mean: Vec<f32>,
+    pub variance: Vec<f32>,
+    // 255 for animated pixels which are never foreground
+    pub flicker: Option<Image<L8>>,
+}
 
-    let bkg_filename = format!("{}{}{}",
-        BACKGROUND_PREFIX,
-        BACKGROUND_FINAL_SUFFIX,
-        EXTENSION);
+impl Background {
+    pub fn from_image(image: Image<Rgb8>) -> Self {
+        let mean = image.data().iter().map(|&v| v as f32).collect();
+        let variance = vec![0.0; image.data().len()];
 
-    if let Some((mut s, _, _)) = img::load(&bkg_filename) {
-        img::bgr_to_rgb(&mut s);
-        return Some(s) // cached
+        Background { image, mean, variance, flicker: None }
     }
 
-    // load samples starting at background0
+    // None if there are no samples or their sizes differ from the image
+    pub fn from_samples(image: Image<Rgb8>, samples: &[Image<Rgb8>]) -> Option<Self> {
+        if samples.is_empty() { return None }
+
+        let len = image.data().len();
+
+        if samples.iter().any(|s| s.data().len() != len) { return None }
+
+        let n = samples.len() as f32;
+
+        let mut mean = vec![0.0; len];
+        let mut variance = vec![0.0; len];
+
+        for s in samples {
+            for (m, &v) in mean.iter_mut().zip(s.data()) {
+                *m += v as f32 / n;
+            }
+        }
+
+        for s in samples {
+            for ((var, &m), &v) in variance.iter_mut().zip(&mean).zip(s.data()) {
+                let d = v as f32 - m;
+                *var += d * d / n;
+            }
+        }
+
+        Some(Background { image, mean, variance, flicker: None })
+    }
+
+    // pixels whose variance summed over the channels is above max_variance
+    pub fn flicker_mask(&self, max_variance: f32) -> Image<L8> {
+        let mask = self.variance.chunks_exact(3)
+            .map(|v| if v.iter().sum::<f32>() > max_variance { u8::MAX } else { u8::MIN })
+            .collect();
+
+        Image::from_raw(self.image.width(), self.image.height(), mask).unwrap()
+    }
+
+    // 255 for foreground pixels, None if img and the background differ in size
+    pub fn foreground(&self, img: &Image<Rgb8>, subtraction: Subtraction) -> Option<Image<L8>> {
+        if img.width() != self.image.width() || img.height() != self.image.height() { return None }
+
+        let mut out = Vec::with_capacity(img.width() * img.height());
+
+        let flicker = self.flicker.as_ref().map(|f| f.data());
+
+        for (i, (p, ((b, mean), variance))) in img.pixels().zip(self.image.pixels()
+            .zip(self.mean.chunks_exact(3))
+            .zip(self.variance.chunks_exact(3)))
+            .enumerate()
+        {
+            let background = flicker.is_some_and(|f| f[i] != 0) || match subtraction {
+                Subtraction::Exact => p == b,
+                Subtraction::Tolerance(tolerance) => p.iter().zip(b).zip(&tolerance)
+                    .all(|((&v, &b), &t)| v.abs_diff(b) <= t),
+                Subtraction::Statistical { threshold, min_variance } => {
+                    let distance: f32 = p.iter().zip(mean).zip(variance)
+                        .map(|((&v, &m), &var)| {
+                            let d = v as f32 - m;
+                            d * d / var.max(min_variance)
+                        })
+                        .sum();
+
+                    distance <= threshold * threshold
+                },
+            };
+
+            out.push(if background { u8::MIN } else { u8::MAX });
+        }
+        Image::from_raw(img.width(), img.height(), out)
+    }
+
+    // img with the background zeroed and the foreground mask, None if they differ in size
+    pub fn remove(&self, img: &Image<Rgb8>, subtraction: Subtraction) -> Option<(Image<Rgb8>, Image<L8>)> {
+        let mask = self.foreground(img, subtraction)?;
+
+        let out = match subtraction {
+            Subtraction::Exact => {
+                let channels = img.data().iter().zip(self.image.data())
+                    .map(|(&v, &b)| if v == b { 0 } else { v })
+                    .collect();
+
+                img::apply_mask(&Image::from_raw(img.width(), img.height(), channels)?, &mask)?
+            },
+            _ => img::apply_mask(img, &mask)?,
+        };
+        Some((out, mask))
+    }
+}
+
+// per pixel over the samples, None without samples or if their sizes differ
+pub fn estimate(samples: &[Image<Rgb8>], estimator: Estimator) -> Option<Image<Rgb8>> {
+    let first = samples.first()?;
+
+    if samples.iter().any(|s| s.width() != first.width() || s.height() != first.height()) { return None }
+
+    let mut out: Image<Rgb8> = Image::new(first.width(), first.height());
+    let mut colors: Vec<[u8; 3]> = Vec::with_capacity(samples.len());
+
+    for (i, o) in out.data_mut().chunks_exact_mut(3).enumerate() {
+        colors.clear();
+        colors.extend(samples.iter().map(|s| {
+            let p = &s.data()[3 * i..3 * i + 3];
+            [p[0], p[1], p[2]]
+        }));
+
+        let color = match estimator {
+            Estimator::Mode => {
+                colors.sort_unstable();
+
+                // longest run, earliest color on ties
+                let mut best = (colors[0], 0);
+                let mut run = (colors[0], 0);
+
+                for &c in &colors {
+                    run = if c == run.0 { (c, run.1 + 1) } else { (c, 1) };
+                    if run.1 > best.1 { best = run }
+                }
+                best.0
+            },
+            Estimator::Median => {
+                colors.sort_unstable_by_key(|c| c.iter().map(|&v| v as u16).sum::<u16>());
+                colors[colors.len() / 2]
+            },
+        };
+
+        o.copy_from_slice(&color);
+    }
+    Some(out)
+}
+
+// background0.png, background1.png, ... until one is missing
+pub fn load_samples(directory: &str) -> Vec<Image<Rgb8>> {
     let mut samples = vec![];
-    let mut width = 0;
-    let mut height = 0;
 
-    while let Some((s, w, h)) = img::load(&format!("{}{}{}", BACKGROUND_PREFIX, samples.len(), EXTENSION)) {
-        width = w;
-        height = h;
-        samples.push(s)
+    while let Some(s) = img::load(&format!("{}/background{}.png", directory, samples.len())) {
+        samples.push(img::bgr_to_rgb(s))
     }
+    samples
+}
+
+// - check for a background_final file
+// - check for background<i> sample files and take their mode
+// - fail
+// samples, when available, also give the mean and variance
+// the flicker mask is read from background_flicker if present
+// see src/bin/background_builder.rs for saving both, and background_adaptive for learning the background without samples
+pub fn fetch_background() -> Option<Background> {
+    let samples = load_samples(BACKGROUND_DIRECTORY);
 
-    if let Some(mut image) = img::mode(&samples) {
-        img::bgr_to_rgb(&mut image);
-        image.save(width, height, image::ColorType::Rgb8,
-            &format!("{}{}{}", BACKGROUND_PREFIX, BACKGROUND_FINAL_SUFFIX, EXTENSION));
-        Some(image)
+    let image = if let Some(s) = img::load(BACKGROUND_FINAL) {
+        eprintln!("Using the background {}", BACKGROUND_FINAL);
+        img::bgr_to_rgb(s)
+    } else if let Some(image) = estimate(&samples, Estimator::Mode) {
+        eprintln!("Using the mode of {} background samples in {}", samples.len(), BACKGROUND_DIRECTORY);
+        image
     } else {
         eprintln!("Unable to load background");
-        None
+        return None
+    };
+
+    let mut background = if samples.is_empty() {
+        Background::from_image(image)
+    } else {
+        Background::from_samples(image.clone(), &samples).unwrap_or_else(|| {
+            eprintln!("Ignoring the background samples: they don't match {}", BACKGROUND_FINAL);
+            Background::from_image(image)
+        })
+    };
+
+    if let Some(flicker) = img::load_luma(BACKGROUND_FLICKER) {
+        if flicker.width() == background.image.width() && flicker.height() == background.image.height() {
+            background.flicker = Some(flicker);
+        } else {
+            eprintln!("Ignoring {}: size doesn't match the background", BACKGROUND_FLICKER);
+        }
+    }
+    Some(background)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mismatched_sizes_have_no_foreground() {
+        let background = Background::from_image(Image::new(4, 3));
+
+        assert!(background.foreground(&Image::new(3, 4), Subtraction::Exact).is_none());
+        assert!(background.remove(&Image::new(4, 4), Subtraction::Exact).is_none());
+        assert!(img::apply_mask(&Image::new(4, 3), &Image::new(3, 4)).is_none());
+    }
+
+    fn pixels(data: &[u8]) -> Image<Rgb8> {
+        Image::from_raw(data.len() / 3, 1, data.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn exact_zeroes_equal_channels() {
+        let background = Background::from_image(pixels(&[10, 20, 30,  10, 20, 30]));
+
+        let (img, mask) = background.remove(&pixels(&[10, 20, 30,  10, 99, 30]), Subtraction::Exact).unwrap();
+
+        assert_eq!(mask.data(), &[0, 255]);
+        assert_eq!(img.data(), &[0, 0, 0,  0, 99, 0]);
+    }
+
+    #[test]
+    fn tolerance_keeps_whole_foreground_pixels() {
+        let background = Background::from_image(pixels(&[10, 20, 30,  10, 20, 30]));
+
+        let (img, mask) = background.remove(&pixels(&[12, 18, 30,  10, 99, 30]), Subtraction::Tolerance([2, 2, 2])).unwrap();
+
+        assert_eq!(mask.data(), &[0, 255]);
+        assert_eq!(img.data(), &[0, 0, 0,  10, 99, 30]);
+    }
+
+    #[test]
+    fn tolerance_is_inclusive_per_channel() {
+        let background = Background::from_image(pixels(&[10, 20, 30]));
+
+        let mask = |p: &[u8]| background.foreground(&pixels(p), Subtraction::Tolerance([2, 4, 0])).unwrap().data()[0];
+
+        assert_eq!(mask(&[8, 24, 30]), 0);
+        assert_eq!(mask(&[12, 16, 30]), 0);
+        assert_eq!(mask(&[7, 20, 30]), 255);
+        assert_eq!(mask(&[10, 25, 30]), 255);
+        assert_eq!(mask(&[10, 20, 31]), 255);
+    }
+
+    #[test]
+    fn statistical_scales_by_the_sample_variance() {
+        // per channel mean 20 and variance 200 / 3
+        let samples = [pixels(&[10, 10, 10]), pixels(&[20, 20, 20]), pixels(&[30, 30, 30])];
+        let background = Background::from_samples(pixels(&[20, 20, 20]), &samples).unwrap();
+
+        let subtraction = Subtraction::Statistical { threshold: 2.0, min_variance: 1.0 };
+        let mask = |p: &[u8]| background.foreground(&pixels(p), subtraction).unwrap().data()[0];
+
+        // 100 / (200 / 3) = 1.5 and 3 of at most 4
+        assert_eq!(mask(&[30, 20, 20]), 0);
+        assert_eq!(mask(&[30, 30, 20]), 0);
+        // 400 / (200 / 3) = 6
+        assert_eq!(mask(&[40, 20, 20]), 255);
+        assert_eq!(mask(&[30, 30, 30]), 255);
+    }
+
+    #[test]
+    fn statistical_raises_variances_to_the_minimum() {
+        // zero variance without samples
+        let background = Background::from_image(pixels(&[10, 20, 30,  10, 20, 30]));
+
+        let subtraction = Subtraction::Statistical { threshold: 1.0, min_variance: 4.0 };
+        let (img, mask) = background.remove(&pixels(&[12, 20, 30,  13, 20, 30]), subtraction).unwrap();
+
+        assert_eq!(mask.data(), &[0, 255]);
+        assert_eq!(img.data(), &[0, 0, 0,  13, 20, 30]);
+    }
+
+    #[test]
+    fn flicker_is_never_foreground() {
+        let mut background = Background::from_image(pixels(&[10, 20, 30,  10, 20, 30]));
+        background.flicker = Some(Image::from_raw(2, 1, vec![255, 0]).unwrap());
+
+        let (img, mask) = background.remove(&pixels(&[99, 99, 99,  99, 20, 30]), Subtraction::Exact).unwrap();
+
+        assert_eq!(mask.data(), &[0, 255]);
+        assert_eq!(img.data(), &[0, 0, 0,  99, 0, 0]);
+    }
+
+    // one sample per pixel list, all of the same length
+    fn stack(pixels_per_sample: &[&[[u8; 3]]]) -> Vec<Image<Rgb8>> {
+        pixels_per_sample.iter()
+            .map(|p| pixels(&p.iter().flatten().copied().collect::<Vec<_>>()))
+            .collect()
+    }
+
+    #[test]
+    fn mode_keeps_triplets_together() {
+        // the most frequent channel values would be [1, 5, 6], which is no sample
+        let samples = stack(&[
+            &[[1, 2, 3], [7, 7, 7]],
+            &[[4, 5, 6], [7, 7, 7]],
+            &[[1, 2, 3], [0, 0, 0]],
+            &[[4, 5, 6], [7, 7, 7]],
+            &[[1, 5, 7], [0, 0, 0]],
+            &[[9, 5, 6], [7, 7, 7]],
+        ]);
+
+        // equally frequent colors go to the smallest
+        assert_eq!(estimate(&samples, Estimator::Mode).unwrap().data(), &[1, 2, 3,  7, 7, 7]);
+    }
+
+    #[test]
+    fn median_picks_the_sample_of_median_brightness() {
+        let samples = stack(&[
+            &[[10, 10, 10]],
+            &[[0, 0, 90]],
+            &[[50, 0, 0]],
+        ]);
+
+        assert_eq!(estimate(&samples, Estimator::Median).unwrap().data(), &[50, 0, 0]);
+    }
+
+    #[test]
+    fn estimates_need_samples_of_one_size() {
+        assert!(estimate(&[], Estimator::Mode).is_none());
+        assert!(estimate(&[Image::new(2, 1), Image::new(1, 2)], Estimator::Median).is_none());
+    }
+
+    #[test]
+    fn flicker_marks_pixels_that_vary() {
+        // the second pixel alternates between black and white, the third barely changes
+        let samples = stack(&[
+            &[[5, 5, 5], [0, 0, 0], [100, 100, 100]],
+            &[[5, 5, 5], [255, 255, 255], [102, 100, 100]],
+        ]);
+
+        let background = Background::from_samples(estimate(&samples, Estimator::Mode).unwrap(), &samples).unwrap();
+
+        assert_eq!(&background.variance[3..6], &[16256.25; 3]);
+        assert_eq!(background.flicker_mask(10.0).data(), &[0, 255, 0]);
+        assert_eq!(background.flicker_mask(0.5).data(), &[0, 255, 255]);
     }
 }
@@ -0,0 +1,328 @@
+use std::io;
+
+use serde::Deserialize;
+
+use crate::img::{Image, L8, Rgb8};
+
+
+// Gaussian mixture per pixel (Stauffer & Grimson) with one variance for all channels
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MixtureParameters {
+    pub components: usize,
+    pub learning_rate: f32,
+    pub initial_variance: f32,
+    pub min_variance: f32,
+    // a pixel matches a component within this many standard deviations
+    pub match_deviations: f32,
+    // the heaviest components that sum to this weight are background
+    pub background_weight: f32,
+}
+
+impl Default for MixtureParameters {
+    fn default() -> Self {
+        MixtureParameters {
+            components: 3,
+            learning_rate: 0.01,
+            initial_variance: 225.0,
+            min_variance: 16.0,
+            match_deviations: 2.5,
+            background_weight: 0.7,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdaptiveConfig {
+    // frames the initial median is taken over, nothing is foreground until then
+    pub warmup_frames: usize,
+    // pixels within the tolerance of the running median are background
+    pub tolerance: [u8; 3],
+    // classifies pixels instead of the tolerance when set
+    pub mixture: Option<MixtureParameters>,
+}
+
+impl Default for AdaptiveConfig {
+    fn default() -> Self {
+        AdaptiveConfig { warmup_frames: 60, tolerance: [8, 8, 8], mixture: None }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Gaussian {
+    weight: f32,
+    mean: [f32; 3],
+    variance: f32,
+}
+
+// Background learned from the frames it is given
+// The running median moves one step per frame toward pixels classified as background.
+// The mixture, unlike the median, also learns from foreground pixels as low weight components
+// so objects that stop moving eventually become background.
+pub struct AdaptiveBackground {
+    config: AdaptiveConfig,
+    // the median of the warmup frames is saved here, nothing is saved without it
+    persist: Option<String>,
+    warmup: Vec<Image<Rgb8>>,
+    median: Option<Image<Rgb8>>,
+    // components per pixel, in pixel order
+    mixture: Vec<Gaussian>,
+}
+
+impl AdaptiveBackground {
+    pub fn new(config: AdaptiveConfig, persist: Option<String>) -> Self {
+        AdaptiveBackground { config, persist, warmup: vec![], median: None, mixture: vec![] }
+    }
+
+    pub fn config(&self) -> &AdaptiveConfig {
+        &self.config
+    }
+
+    // None while warming up
+    pub fn background(&self) -> Option<&Image<Rgb8>> {
+        self.median.as_ref()
+    }
+
+    // foreground mask of img, None while warming up
+    pub fn update(&mut self, img: &Image<Rgb8>) -> Option<Image<L8>> {
+        let median = match &mut self.median {
+            Some(median) if median.data().len() == img.data().len() => median,
+            Some(_) => {
+                eprintln!("Frame size changed, relearning the background");
+                self.median = None;
+                self.warmup.clear();
+                return self.update(img);
+            },
+            None => {
+                self.warmup.push(img.clone());
+
+                if self.warmup.len() < self.config.warmup_frames.max(1) { return None }
+
+                self.initialize();
+                return Some(Image::new(img.width(), img.height()));
+            },
+        };
+
+        let mask = match &self.config.mixture {
+            Some(parameters) => update_mixture(&mut self.mixture, parameters, img),
+            None => classify_tolerance(median, img, &self.config.tolerance),
+        };
+
+        // step toward the frame
+        for ((m, p), &f) in median.data_mut().chunks_exact_mut(3).zip(img.pixels()).zip(mask.data()) {
+            if f != 0 { continue }
+
+            for (m, &v) in m.iter_mut().zip(p) {
+                *m = if v > *m { *m + 1 } else if v < *m { *m - 1 } else { *m };
+            }
+        }
+        Some(mask)
+    }
+
+    // saves the running median to persist, does nothing while warming up or without it
+    pub fn persist(&self) -> io::Result<()> {
+        if let (Some(filename), Some(median)) = (&self.persist, &self.median) {
+            if let Some(directory) = std::path::Path::new(filename).parent() {
+                std::fs::create_dir_all(directory)?;
+            }
+            median.try_save(filename).map_err(io::Error::other)?;
+        }
+        Ok(())
+    }
+
+    // per channel median of the warmup frames
+    fn initialize(&mut self) {
+        let warmup = std::mem::take(&mut self.warmup);
+        let first = &warmup[0];
+
+        let mut median: Image<Rgb8> = Image::new(first.width(), first.height());
+        let mut samples = Vec::with_capacity(warmup.len());
+
+        for (i, m) in median.data_mut().iter_mut().enumerate() {
+            samples.clear();
+            samples.extend(warmup.iter().filter_map(|w| w.data().get(i)));
+            samples.sort_unstable();
+
+            *m = samples[samples.len() / 2];
+        }
+
+        if let Some(parameters) = &self.config.mixture {
+            let components = parameters.components.max(1);
+
+            self.mixture = median.pixels()
+                .flat_map(|p| (0..components).map(move |k| Gaussian {
+                    weight: if k == 0 { 1.0 } else { 0.0 },
+                    mean: [p[0] as f32, p[1] as f32, p[2] as f32],
+                    variance: parameters.initial_variance,
+                }))
+                .collect();
+        }
+
+        self.median = Some(median);
+
+        match (&self.persist, self.persist()) {
+            (_, Err(e)) => eprintln!("Unable to persist the background: {}", e),
+            (Some(filename), Ok(())) => eprintln!("Saved the learned background to {}", filename),
+            (None, Ok(())) => {},
+        }
+    }
+}
+
+fn classify_tolerance(median: &Image<Rgb8>, img: &Image<Rgb8>, tolerance: &[u8; 3]) -> Image<L8> {
+    let mask = img.pixels().zip(median.pixels())
+        .map(|(p, m)| {
+            let background = p.iter().zip(m).zip(tolerance).all(|((&v, &m), &t)| v.abs_diff(m) <= t);
+
+            if background { u8::MIN } else { u8::MAX }
+        })
+        .collect();
+
+    Image::from_raw(img.width(), img.height(), mask).unwrap()
+}
+
+fn update_mixture(mixture: &mut [Gaussian], parameters: &MixtureParameters, img: &Image<Rgb8>) -> Image<L8> {
+    let components = parameters.components.max(1);
+    let alpha = parameters.learning_rate;
+
+    let mut mask = Vec::with_capacity(img.width() * img.height());
+
+    for (gaussians, p) in mixture.chunks_exact_mut(components).zip(img.pixels()) {
+        let x = [p[0] as f32, p[1] as f32, p[2] as f32];
+
+        // most likely background first
+        gaussians.sort_by(|a, b| (b.weight / b.variance.sqrt()).total_cmp(&(a.weight / a.variance.sqrt())));
+
+        let mut cumulative = 0.0;
+        let background_count = gaussians.iter()
+            .take_while(|g| { let below = cumulative < parameters.background_weight; cumulative += g.weight; below })
+            .count();
+
+        let distance = |g: &Gaussian| x.iter().zip(&g.mean).map(|(v, m)| (v - m) * (v - m)).sum::<f32>();
+
+        let matched = gaussians.iter()
+            .position(|g| g.weight > 0.0 && distance(g) < parameters.match_deviations.powi(2) * g.variance * 3.0);
+
+        mask.push(match matched {
+            Some(k) if k < background_count => u8::MIN,
+            _ => u8::MAX,
+        });
+
+        for (k, g) in gaussians.iter_mut().enumerate() {
+            let hit = matched == Some(k);
+
+            g.weight = (1.0 - alpha) * g.weight + if hit { alpha } else { 0.0 };
+
+            if hit {
+                let d = distance(g);
+
+                for (m, v) in g.mean.iter_mut().zip(&x) {
+                    *m += alpha * (v - *m);
+                }
+                g.variance = (g.variance + alpha * (d / 3.0 - g.variance)).max(parameters.min_variance);
+            }
+        }
+
+        // replace the least likely component
+        if matched.is_none() {
+            let last = gaussians.len() - 1;
+            gaussians[last] = Gaussian { weight: alpha, mean: x, variance: parameters.initial_variance };
+        }
+
+        let total: f32 = gaussians.iter().map(|g| g.weight).sum();
+
+        for g in gaussians.iter_mut() {
+            g.weight /= total;
+        }
+    }
+    Image::from_raw(img.width(), img.height(), mask).unwrap()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(mixture: Option<MixtureParameters>) -> AdaptiveConfig {
+        AdaptiveConfig { warmup_frames: 3, tolerance: [8, 8, 8], mixture }
+    }
+
+    fn frame(value: u8) -> Image<Rgb8> {
+        Image::from_raw(2, 1, vec![value; 6]).unwrap()
+    }
+
+    #[test]
+    fn persists_once_after_the_warmup() {
+        let filename = std::env::temp_dir()
+            .join(format!("bumper_robot_adaptive_{}", std::process::id()))
+            .join("background.png");
+        let _ = std::fs::remove_file(&filename);
+
+        let mut background = AdaptiveBackground::new(config(None), Some(filename.to_str().unwrap().to_string()));
+
+        assert!(background.update(&frame(10)).is_none());
+        assert!(background.update(&frame(30)).is_none());
+        assert!(!filename.exists());
+
+        assert_eq!(background.update(&frame(20)).unwrap().data(), &[0, 0]);
+        assert_eq!(crate::img::load(filename.to_str().unwrap()).unwrap().data(), &[20; 6]);
+
+        // the running median moves, the saved one doesn't
+        std::fs::remove_file(&filename).unwrap();
+        background.update(&frame(22));
+        drop(background);
+
+        assert!(!filename.exists());
+    }
+
+    #[test]
+    fn persisting_fails_without_panicking() {
+        let blocker = std::env::temp_dir().join(format!("bumper_robot_adaptive_blocker_{}", std::process::id()));
+        std::fs::write(&blocker, "not a directory").unwrap();
+
+        let mut background = AdaptiveBackground::new(config(None), Some(blocker.join("background.png").to_str().unwrap().to_string()));
+
+        for _ in 0..3 { background.update(&frame(10)); }
+
+        assert!(background.persist().is_err());
+        assert!(background.background().is_some());
+
+        std::fs::remove_file(blocker).unwrap();
+    }
+
+    #[test]
+    fn nothing_is_persisted_without_a_filename() {
+        let mut background = AdaptiveBackground::new(config(None), None);
+
+        for _ in 0..3 { background.update(&frame(10)); }
+
+        assert!(background.persist().is_ok());
+    }
+
+    #[test]
+    fn mixture_matches_the_warmup_colors() {
+        let mut background = AdaptiveBackground::new(config(Some(MixtureParameters::default())), None);
+
+        for _ in 0..3 { background.update(&frame(10)); }
+
+        assert_eq!(background.update(&frame(20)).unwrap().data(), &[0, 0]);
+        assert_eq!(background.update(&frame(200)).unwrap().data(), &[255, 255]);
+        assert_eq!(background.update(&frame(12)).unwrap().data(), &[0, 0]);
+    }
+
+    #[test]
+    fn mixture_learns_objects_that_stop_moving() {
+        let parameters = MixtureParameters { learning_rate: 0.2, ..MixtureParameters::default() };
+        let mut background = AdaptiveBackground::new(config(Some(parameters)), None);
+
+        for _ in 0..3 { background.update(&frame(10)); }
+
+        // the new color's weight grows by the learning rate each frame until it is among the background_weight heaviest
+        let masks = (0..4).map(|_| background.update(&frame(200)).unwrap().data()[0]).collect::<Vec<_>>();
+
+        assert_eq!(masks, vec![255, 255, 0, 0]);
+
+        // the warmup color is still known
+        assert_eq!(background.update(&frame(10)).unwrap().data(), &[0, 0]);
+    }
+}
@@ -0,0 +1,43 @@
+use bumper_robot::background::{self, BACKGROUND_DIRECTORY, Background, Estimator};
+
+
+// Combines background0.png, background1.png, ... from a sample directory into
+// background_final.png and background_flicker.png for the robot
+fn main() {
+    const DEFAULT_MAX_VARIANCE: f32 = 100.0;
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let argument = |long: &str| args.iter()
+        .position(|a| a == long)
+        .map(|i| args.get(i + 1).unwrap_or_else(|| panic!("Missing value for {}", long)).as_str());
+
+    let directory = args.first()
+        .filter(|a| !a.starts_with("--"))
+        .unwrap_or_else(|| panic!("Usage: background_builder <sample directory> [--median] [--flicker <max variance>] [--output <directory>]"));
+
+    let estimator = if args.iter().any(|a| a == "--median") { Estimator::Median } else { Estimator::Mode };
+
+    let max_variance = argument("--flicker")
+        .map_or(DEFAULT_MAX_VARIANCE, |v| v.parse().unwrap_or_else(|_| panic!("Invalid flicker variance {}", v)));
+
+    let output = argument("--output").unwrap_or(BACKGROUND_DIRECTORY);
+
+    let samples = background::load_samples(directory);
+
+    let image = background::estimate(&samples, estimator)
+        .unwrap_or_else(|| panic!("No background samples of a single size in {}", directory));
+
+    let background = Background::from_samples(image, &samples).unwrap();
+
+    let flicker = background.flicker_mask(max_variance);
+
+    std::fs::create_dir_all(output).unwrap_or_else(|e| panic!("Unable to create {}: {}", output, e));
+
+    background.image.save(&format!("{}/background_final.png", output));
+    flicker.save(&format!("{}/background_flicker.png", output));
+
+    let flickering = flicker.data().iter().filter(|&&f| f != 0).count();
+
+    println!("{} samples, {} of {} pixels flicker", samples.len(), flickering, flicker.data().len());
+}
@@ -0,0 +1,123 @@
+use std::{path::PathBuf, time::Duration};
+
+use crate::{capture::{Frame, FrameSource}, clock::{Clock, SimulatedClock}, coordinates::{Rectangle, Screen}, img::{self, Image, Rgb8}};
+
+
+// Replays previously captured frames of the active area
+// - <directory>/active_area.txt holds the screen rectangle as "left top width height"
+// - <directory>/frame<i>.png starting at frame0
+pub struct DirectoryCapture {
+    directory: PathBuf,
+    next: usize,
+    clock: SimulatedClock,
+
+    pub active_area: Rectangle<usize, Screen>,
+    pub frame_interval: Duration,
+}
+
+impl DirectoryCapture {
+    pub fn new(directory: &str) -> Option<Self> {
+        const ACTIVE_AREA_FILENAME: &str = "active_area.txt";
+
+        let directory = PathBuf::from(directory);
+
+        let active_area = std::fs::read_to_string(directory.join(ACTIVE_AREA_FILENAME)).ok()
+            .and_then(|s| s.parse().ok())
+            .or_else(|| {
+                eprintln!("Unable to read {} in {}", ACTIVE_AREA_FILENAME, directory.display());
+                None
+            })?;
+
+        Some(DirectoryCapture {
+            directory,
+            next: 0,
+            clock: SimulatedClock::new(),
+            active_area,
+            frame_interval: Duration::from_secs(1) / 60,
+        })
+    }
+
+    fn frame_path(&self, i: usize) -> PathBuf {
+        self.directory.join(format!("frame{}.png", i))
+    }
+}
+
+impl FrameSource for DirectoryCapture {
+    fn try_frame(&mut self) -> Option<Frame> {
+        loop {
+            let path = self.frame_path(self.next);
+
+            let img: Image<Rgb8> = img::bgr_to_rgb(img::load(path.to_str()?)?);
+
+            self.next += 1;
+
+            if img.width() != self.active_area.width || img.height() != self.active_area.height {
+                eprintln!("Skipping {}: size {}x{} doesn't match the active area", path.display(), img.width(), img.height());
+                continue
+            }
+
+            // frame<i> was captured at i * frame_interval
+            self.clock.set(self.frame_interval * (self.next - 1) as u32);
+
+            return Some(Frame { img, active_area: self.active_area.clone(), t: self.clock.now() })
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a fresh directory holding active_area.txt and one solid frame per size
+    fn fixture(name: &str, active_area: &str, sizes: &[(usize, usize)]) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("bumper_robot_{}_{}", name, std::process::id()));
+
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("active_area.txt"), active_area).unwrap();
+
+        for (i, (width, height)) in sizes.iter().enumerate() {
+            image::save_buffer(directory.join(format!("frame{}.png", i)), &vec![i as u8; width * height * 3],
+                *width as u32, *height as u32, image::ColorType::Rgb8).unwrap();
+        }
+        directory
+    }
+
+    #[test]
+    fn frames_are_timestamped_by_index() {
+        let directory = fixture("timestamps", "10 20 4 3", &[(4, 3), (4, 3), (4, 3)]);
+
+        let mut source = DirectoryCapture::new(directory.to_str().unwrap()).unwrap();
+
+        assert_eq!(source.active_area, Rectangle::new(10, 20, 4, 3));
+
+        let times = std::iter::from_fn(|| source.try_frame()).map(|f| f.t).collect::<Vec<_>>();
+
+        assert_eq!(times, vec![Duration::ZERO, source.frame_interval, source.frame_interval * 2]);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn mismatched_frames_are_skipped_but_keep_their_time_slot() {
+        let directory = fixture("mismatched", "0 0 4 3", &[(4, 3), (5, 3), (4, 3)]);
+
+        let mut source = DirectoryCapture::new(directory.to_str().unwrap()).unwrap();
+
+        let frames = std::iter::from_fn(|| source.try_frame()).collect::<Vec<_>>();
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].t, source.frame_interval * 2);
+        assert_eq!(frames[1].img.data()[0], 2);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn missing_active_area_fails() {
+        let directory = std::env::temp_dir().join(format!("bumper_robot_missing_{}", std::process::id()));
+
+        assert!(DirectoryCapture::new(directory.to_str().unwrap()).is_none());
+    }
+}
@@ -1,14 +1,73 @@
+use std::time::Duration;
+
+use scrap::{Capturer, Display};
 use winapi::{shared::windef, um::winuser as win};
 
-use crate::{capture::Capture, img::Rectangle};
+use crate::{capture::{Frame, FrameSource, active_rectangle}, clock::Clock, coordinates::{Rectangle, Screen}, img::{Image, Rgb8}, input::{InputSink, MouseCommand}};
+
+
+pub struct Capture {
+    capturer: Capturer,
+    clock: Box<dyn Clock>,
+
+    pub window: Rectangle<usize, Screen>,
+}
+
+impl Capture {
+    pub fn new(window_title: &str, clock: impl Clock + 'static) -> Option<Self> {
+        let capturer = Capturer::new(
+            Display::primary().expect("Couldn't find primary display.")
+        ).expect("Couldn't initialize screen capturing");
+
+        let clock = Box::new(clock);
+
+        Capture::find_window(window_title).map(|window| Capture{capturer, clock, window})
+    }
+}
+
+impl FrameSource for Capture {
+    fn try_frame(&mut self) -> Option<Frame> {
+        let screen_width = self.capturer.width();
+        let window = &self.window;
+        let clock = &self.clock;
+
+        self.capturer.frame().map_or(None, |buffer| {
+            let t = clock.now();
+
+            let active_area = active_rectangle(window)?;
+
+            let img = copy_rectangle_bgr_to_rgb(&buffer, screen_width, &active_area);
+
+            Some(Frame { img, active_area, t })
+        })
+    }
+}
+
+// BGRA screen buffer -> RGB
+// no bound checks
+fn copy_rectangle_bgr_to_rgb(data: &[u8], stride: usize, rectangle: &Rectangle<usize, Screen>) -> Image<Rgb8> {
+    let mut img = Image::new(rectangle.width, rectangle.height);
+    let r = img.data_mut();
 
+    for y in 0..rectangle.height {
+        for x in 0..rectangle.width {
+            let idx_dst = 3 * (rectangle.width * y + x);
+            let idx_src = 4 * (stride * (rectangle.top + y) + rectangle.left + x);
+
+            r[idx_dst    ] = data[idx_src + 2];
+            r[idx_dst + 1] = data[idx_src + 1];
+            r[idx_dst + 2] = data[idx_src    ];
+        }
+    }
+    img
+}
 
 pub trait FindWindow {
-    fn find_window(window_name: &str) -> Option<Rectangle<usize>>;
+    fn find_window(window_name: &str) -> Option<Rectangle<usize, Screen>>;
 }
 
 impl FindWindow for Capture {
-    fn find_window(window_name: &str) -> Option<Rectangle<usize>> {
+    fn find_window(window_name: &str) -> Option<Rectangle<usize, Screen>> {
         let window_name = std::ffi::CString::new(window_name).unwrap();
 
         unsafe {
@@ -23,74 +82,69 @@ impl FindWindow for Capture {
             let lprect = &mut rect as *mut windef::RECT;
             win::GetWindowRect(handle, lprect);
 
-            Some( Rectangle {
-                top:     rect.top                 as usize,
-                left:    rect.left                as usize,
-                width:  (rect.right  - rect.left) as usize,
-                height: (rect.bottom - rect.top)  as usize,
-            })
-        }
-    }
-}
-
-enum MouseAction {
-    Move { x: usize, y: usize },
-    Press,
-    Release,
-}
+            // clip parts of the window that are off the top left of the screen
+            let left = rect.left.max(0);
+            let top = rect.top.max(0);
 
-impl MouseAction {
-    fn send(&self) {
-        // TODO: fetch dynamically from Capture.Capturer.width or elsewhere
-        const SCREEN_WIDTH: usize = 3840;
-        const SCREEN_HEIGHT: usize = 2160;
-
-        let input_union = {
-            let mut data = unsafe { std::mem::zeroed::<win::INPUT_u>() };
-            let binding = unsafe { data.mi_mut() };
-
-            binding.dwFlags = win::MOUSEEVENTF_ABSOLUTE;
-
-            match self {
-                MouseAction::Move { x, y } => {
-                    binding.dx = (x * u16::MAX as usize / SCREEN_WIDTH) as i32;
-                    binding.dy = (y * u16::MAX as usize / SCREEN_HEIGHT) as i32;
-
-                    binding.dwFlags |= win::MOUSEEVENTF_MOVE;
-                },
-                MouseAction::Press => {
-                    binding.dwFlags |= win::MOUSEEVENTF_LEFTDOWN;
-                },
-                MouseAction::Release => {
-                    binding.dwFlags |= win::MOUSEEVENTF_LEFTUP;
-                },
-            }
-            data
-        };
-
-        let mut input = win::INPUT {
-            type_: win::INPUT_MOUSE,
-            u: input_union,
-        };
+            if rect.right <= left || rect.bottom <= top { return None }
 
-        unsafe {
-            win::SendInput(
-                1,
-                &mut input as *mut win::INPUT,
-                std::mem::size_of::<win::INPUT>() as i32
-            );
+            Some( Rectangle::new(
+                 left                as usize,
+                 top                 as usize,
+                (rect.right  - left) as usize,
+                (rect.bottom - top)  as usize,
+            ))
         }
     }
 }
 
-pub fn mouse_move(x: usize, y: usize) {
-    MouseAction::Move{ x, y }.send()
+fn send_mouse_command(command: &MouseCommand) {
+    // TODO: fetch dynamically from Capture.Capturer.width or elsewhere
+    const SCREEN_WIDTH: usize = 3840;
+    const SCREEN_HEIGHT: usize = 2160;
+
+    let input_union = {
+        let mut data = unsafe { std::mem::zeroed::<win::INPUT_u>() };
+        let binding = unsafe { data.mi_mut() };
+
+        binding.dwFlags = win::MOUSEEVENTF_ABSOLUTE;
+
+        match command {
+            MouseCommand::Move { x, y } => {
+                binding.dx = (x * u16::MAX as usize / SCREEN_WIDTH) as i32;
+                binding.dy = (y * u16::MAX as usize / SCREEN_HEIGHT) as i32;
+
+                binding.dwFlags |= win::MOUSEEVENTF_MOVE;
+            },
+            MouseCommand::Press => {
+                binding.dwFlags |= win::MOUSEEVENTF_LEFTDOWN;
+            },
+            MouseCommand::Release => {
+                binding.dwFlags |= win::MOUSEEVENTF_LEFTUP;
+            },
+        }
+        data
+    };
+
+    let mut input = win::INPUT {
+        type_: win::INPUT_MOUSE,
+        u: input_union,
+    };
+
+    unsafe {
+        win::SendInput(
+            1,
+            &mut input as *mut win::INPUT,
+            std::mem::size_of::<win::INPUT>() as i32
+        );
+    }
 }
 
-pub fn mouse_press() {
-    MouseAction::Press.send()
-}
+// Sends mouse commands with SendInput
+pub struct WindowsInput;
 
-pub fn mouse_release() {
-    MouseAction::Release.send()
+impl InputSink for WindowsInput {
+    fn send(&mut self, _t: Duration, command: &MouseCommand) {
+        send_mouse_command(command)
+    }
 }
@@ -0,0 +1,93 @@
+use std::{cell::Cell, rc::Rc, time::{Duration, Instant}};
+
+
+// Source of frame timestamps
+// now() is the time since the clock's epoch, clones share the epoch
+pub trait Clock {
+    fn now(&self) -> Duration;
+}
+
+#[derive(Clone, Copy)]
+pub struct SystemClock {
+    epoch: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        SystemClock { epoch: Instant::now() }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.epoch.elapsed()
+    }
+}
+
+// Only moves when told to, for tests and replays
+// clones share the current time
+#[derive(Clone, Default)]
+pub struct SimulatedClock {
+    t: Rc<Cell<Duration>>,
+}
+
+impl SimulatedClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&self, t: Duration) {
+        self.t.set(t);
+    }
+
+    pub fn advance(&self, dt: Duration) {
+        self.t.set(self.t.get() + dt);
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now(&self) -> Duration {
+        self.t.get()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simulated_clock_only_moves_when_told() {
+        let clock = SimulatedClock::new();
+        let shared = clock.clone();
+
+        assert_eq!(clock.now(), Duration::ZERO);
+
+        clock.advance(Duration::from_millis(5));
+        shared.advance(Duration::from_millis(10));
+
+        assert_eq!(clock.now(), Duration::from_millis(15));
+        assert_eq!(shared.now(), Duration::from_millis(15));
+
+        shared.set(Duration::from_secs(2));
+
+        assert_eq!(clock.now(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn system_clock_is_monotonic() {
+        let clock = SystemClock::new();
+        let copy = clock;
+
+        let a = clock.now();
+        let b = copy.now();
+
+        assert!(b >= a);
+    }
+}
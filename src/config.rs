@@ -0,0 +1,617 @@
+use std::{collections::HashSet, fmt, io, time::SystemTime};
+
+use serde::Deserialize;
+
+use crate::{background::Subtraction, background_adaptive::AdaptiveConfig, detector::{HEAD, PLAYER}, img_color::{ColorClass, ColorRange}, img_morphology::Cleanup, kalman::{DEFAULT_MEASUREMENT_NOISE, DEFAULT_PROCESS_NOISE}};
+
+
+// read by the binary when present
+pub const DETECTOR_CONFIG: &str = "./detectors.toml";
+
+// used when there is no config file
+const DEFAULT_CONFIG: &str = include_str!("../detectors.toml");
+
+// Detector configuration, see detectors.toml for the format
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub background: BackgroundConfig,
+    #[serde(default)]
+    pub tracker: TrackerConfig,
+    #[serde(default)]
+    pub projectile_tracker: ProjectileTrackerConfig,
+    #[serde(default)]
+    pub prediction: PredictionConfig,
+    pub head: HeadConfig,
+    // not detected when missing
+    #[serde(default)]
+    pub player: Option<PlayerConfig>,
+    // in output order
+    #[serde(rename = "projectile", default)]
+    pub projectiles: Vec<ProjectileConfig>,
+}
+
+// How frames are separated from the background
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackgroundConfig {
+    // against a saved background
+    pub subtraction: Subtraction,
+    // learned while running when there is no saved background
+    pub learned: AdaptiveConfig,
+}
+
+impl Default for BackgroundConfig {
+    fn default() -> Self {
+        BackgroundConfig { subtraction: Subtraction::Exact, learned: AdaptiveConfig::default() }
+    }
+}
+
+// KalmanFilter noise
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TrackerConfig {
+    pub process_noise: f32,
+    pub measurement_noise: f32,
+}
+
+impl Default for TrackerConfig {
+    fn default() -> Self {
+        TrackerConfig {
+            process_noise: DEFAULT_PROCESS_NOISE,
+            measurement_noise: DEFAULT_MEASUREMENT_NOISE,
+        }
+    }
+}
+
+// Associates projectile detections across frames, see tracker::Tracker
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectileTrackerConfig {
+    // KalmanFilter noise of every track
+    pub process_noise: f32,
+    pub measurement_noise: f32,
+    // largest squared Mahalanobis distance of a detection from its track's prediction
+    pub gate: f32,
+    // consecutive detections before a track is reported
+    pub confirm_hits: usize,
+    // consecutive frames a confirmed track survives without a detection
+    pub max_misses: usize,
+}
+
+impl Default for ProjectileTrackerConfig {
+    fn default() -> Self {
+        ProjectileTrackerConfig {
+            process_noise: DEFAULT_PROCESS_NOISE,
+            measurement_noise: DEFAULT_MEASUREMENT_NOISE,
+            // chi-squared with 2 degrees of freedom at 99%
+            gate: 9.21,
+            confirm_hits: 3,
+            max_misses: 5,
+        }
+    }
+}
+
+// Forward simulation of tracked projectiles, see prediction::Prediction
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PredictionConfig {
+    // seconds ahead
+    pub horizon: f32,
+    // seconds between path samples
+    pub step: f32,
+    // distance between the projectile's and the player's centers that counts as a hit
+    pub impact_radius: f32,
+}
+
+impl Default for PredictionConfig {
+    fn default() -> Self {
+        PredictionConfig { horizon: 1.0, step: 1.0 / 60.0, impact_radius: 6.0 }
+    }
+}
+
+// the largest blob is the boss' blue head
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HeadConfig {
+    pub colors: ColorClass,
+    #[serde(default)]
+    pub cleanup: Vec<Cleanup>,
+    // size of the tracked bounding box
+    pub bbox_width: usize,
+    pub bbox_height: usize,
+}
+
+// the blob closest to where the player is expected, the largest one until first seen
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlayerConfig {
+    pub colors: ColorClass,
+    #[serde(default)]
+    pub cleanup: Vec<Cleanup>,
+    // exclusive bounds
+    pub min_area: usize,
+    pub max_area: usize,
+    // largest squared Mahalanobis distance of a blob from the predicted position
+    pub gate: f32,
+    // consecutive frames the position is only predicted before the player is looked for anew
+    pub max_misses: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectileConfig {
+    pub name: String,
+    pub colors: ColorClass,
+    #[serde(default)]
+    pub cleanup: Vec<Cleanup>,
+    pub shape: ShapeFilter,
+}
+
+// components pass when all bounds hold, bounds are exclusive
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ShapeFilter {
+    pub min_area: usize,
+    pub max_area: usize,
+    // allowed deviation of the bounding box' width / height from 1
+    pub roundness: f32,
+    #[serde(default)]
+    pub min_circularity: Option<f32>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(String, io::Error),
+    Parse(String, toml::de::Error),
+    // detector name and what is wrong with it
+    Invalid(String, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(filename, e) => write!(f, "unable to read {}: {}", filename, e),
+            ConfigError::Parse(filename, e) => write!(f, "unable to parse {}: {}", filename, e),
+            ConfigError::Invalid(detector, reason) => write!(f, "detector {}: {}", detector, reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::parse(DEFAULT_CONFIG, "default config").expect("Invalid default config")
+    }
+}
+
+impl Config {
+    pub fn load(filename: &str) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(filename)
+            .map_err(|e| ConfigError::Io(filename.to_string(), e))?;
+
+        Config::parse(&text, filename)
+    }
+
+    // source names the text in errors
+    pub fn parse(text: &str, source: &str) -> Result<Self, ConfigError> {
+        let config: Config = toml::from_str(text)
+            .map_err(|e| ConfigError::Parse(source.to_string(), e))?;
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |detector: &str, reason: String| Err(ConfigError::Invalid(detector.to_string(), reason));
+
+        if let Subtraction::Statistical { threshold, min_variance } = self.background.subtraction {
+            if !(threshold.is_finite() && threshold > 0.0 && min_variance.is_finite() && min_variance > 0.0) {
+                return invalid("background", "threshold and min_variance must be positive".to_string());
+            }
+        }
+
+        let learned = &self.background.learned;
+
+        if learned.warmup_frames == 0 {
+            return invalid("background", "learned.warmup_frames must be at least 1".to_string());
+        }
+        if let Some(mixture) = &learned.mixture {
+            let positive = [mixture.initial_variance, mixture.min_variance, mixture.match_deviations];
+
+            if mixture.components == 0 {
+                return invalid("background", "learned.mixture.components must be at least 1".to_string());
+            }
+            if positive.iter().any(|n| !n.is_finite() || *n <= 0.0) {
+                return invalid("background", "learned.mixture initial_variance, min_variance and match_deviations must be positive".to_string());
+            }
+            if !(mixture.learning_rate > 0.0 && mixture.learning_rate <= 1.0 && mixture.background_weight > 0.0 && mixture.background_weight <= 1.0) {
+                return invalid("background", "learned.mixture learning_rate and background_weight must be in (0, 1]".to_string());
+            }
+        }
+
+        let noise = [self.tracker.process_noise, self.tracker.measurement_noise];
+
+        if noise.iter().any(|n| !n.is_finite() || *n <= 0.0) {
+            return invalid("tracker", "process_noise and measurement_noise must be positive".to_string());
+        }
+
+        let tracker = &self.projectile_tracker;
+        let positive = [tracker.process_noise, tracker.measurement_noise, tracker.gate];
+
+        if positive.iter().any(|n| !n.is_finite() || *n <= 0.0) {
+            return invalid("projectile_tracker", "process_noise, measurement_noise and gate must be positive".to_string());
+        }
+        if tracker.confirm_hits == 0 {
+            return invalid("projectile_tracker", "confirm_hits must be at least 1".to_string());
+        }
+
+        // keeps paths short enough to compute every frame
+        const MAX_PREDICTION_STEPS: f32 = 1000.0;
+
+        let prediction = &self.prediction;
+
+        if !(prediction.horizon > 0.0 && prediction.step > 0.0 && prediction.impact_radius >= 0.0) {
+            return invalid("prediction", "horizon and step must be positive and impact_radius non negative".to_string());
+        }
+        if prediction.horizon / prediction.step > MAX_PREDICTION_STEPS {
+            return invalid("prediction", format!("horizon / step must be at most {}", MAX_PREDICTION_STEPS));
+        }
+
+        validate_colors(HEAD, &self.head.colors)?;
+        validate_cleanup(HEAD, &self.head.cleanup)?;
+
+        if self.head.bbox_width == 0 || self.head.bbox_height == 0 {
+            return invalid(HEAD, "bbox_width and bbox_height must be positive".to_string());
+        }
+
+        if let Some(player) = &self.player {
+            validate_colors(PLAYER, &player.colors)?;
+            validate_cleanup(PLAYER, &player.cleanup)?;
+
+            if player.min_area >= player.max_area {
+                return invalid(PLAYER, format!("min_area {} must be less than max_area {}", player.min_area, player.max_area));
+            }
+
+            if !player.gate.is_finite() || player.gate <= 0.0 {
+                return invalid(PLAYER, "gate must be positive".to_string());
+            }
+            if player.max_misses == 0 {
+                return invalid(PLAYER, "max_misses must be at least 1".to_string());
+            }
+        }
+
+        let mut names = HashSet::new();
+
+        for p in &self.projectiles {
+            if p.name.is_empty() || p.name.contains(char::is_whitespace) {
+                return invalid(&p.name, "name must be non empty without whitespace".to_string());
+            }
+            if p.name == HEAD || p.name == PLAYER {
+                return invalid(&p.name, "name is reserved".to_string());
+            }
+            if !names.insert(p.name.as_str()) {
+                return invalid(&p.name, "name is used more than once".to_string());
+            }
+
+            validate_colors(&p.name, &p.colors)?;
+            validate_cleanup(&p.name, &p.cleanup)?;
+
+            let shape = &p.shape;
+
+            if shape.min_area >= shape.max_area {
+                return invalid(&p.name, format!("min_area {} must be less than max_area {}", shape.min_area, shape.max_area));
+            }
+            if !(0.0..1.0).contains(&shape.roundness) {
+                return invalid(&p.name, format!("roundness {} must be in [0, 1)", shape.roundness));
+            }
+            if let Some(c) = shape.min_circularity {
+                if !(0.0..=1.0).contains(&c) {
+                    return invalid(&p.name, format!("min_circularity {} must be in [0, 1]", c));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // human readable differences to another config
+    pub fn changes(&self, new: &Config) -> Vec<String> {
+        let mut changes = vec![];
+
+        if self.background != new.background {
+            changes.push(format!("background: {:?} -> {:?}", self.background, new.background));
+        }
+        if self.tracker != new.tracker {
+            changes.push(format!("tracker: {:?} -> {:?}", self.tracker, new.tracker));
+        }
+        if self.projectile_tracker != new.projectile_tracker {
+            changes.push(format!("projectile_tracker: {:?} -> {:?}", self.projectile_tracker, new.projectile_tracker));
+        }
+        if self.prediction != new.prediction {
+            changes.push(format!("prediction: {:?} -> {:?}", self.prediction, new.prediction));
+        }
+        if self.head != new.head {
+            changes.push(format!("head: {:?} -> {:?}", self.head, new.head));
+        }
+        if self.player != new.player {
+            changes.push(format!("player: {:?} -> {:?}", self.player, new.player));
+        }
+
+        for p in &self.projectiles {
+            match new.projectiles.iter().find(|n| n.name == p.name) {
+                None => changes.push(format!("{}: removed", p.name)),
+                Some(n) => {
+                    if p.colors != n.colors { changes.push(format!("{} colors: {:?} -> {:?}", p.name, p.colors, n.colors)) }
+                    if p.cleanup != n.cleanup { changes.push(format!("{} cleanup: {:?} -> {:?}", p.name, p.cleanup, n.cleanup)) }
+                    if p.shape != n.shape { changes.push(format!("{} shape: {:?} -> {:?}", p.name, p.shape, n.shape)) }
+                },
+            }
+        }
+        for n in &new.projectiles {
+            if !self.projectiles.iter().any(|p| p.name == n.name) {
+                changes.push(format!("{}: added", n.name));
+            }
+        }
+
+        let names = |c: &Config| c.projectiles.iter().map(|p| p.name.clone()).collect::<Vec<_>>();
+
+        if changes.is_empty() && names(self) != names(new) {
+            changes.push(format!("projectile order: {:?} -> {:?}", names(self), names(new)));
+        }
+        changes
+    }
+}
+
+// Reloads a config file whenever its modification time changes
+pub struct ConfigWatcher {
+    filename: String,
+    // last version loaded successfully
+    loaded: Stamp,
+    // last version that failed to load, reported once
+    failed: Option<Stamp>,
+}
+
+// modification time and length, None for a missing file
+// an edit caught halfway through usually differs from the finished one in length
+type Stamp = Option<(SystemTime, u64)>;
+
+impl ConfigWatcher {
+    // the current contents count as loaded
+    pub fn new(filename: &str) -> Self {
+        ConfigWatcher { filename: filename.to_string(), loaded: stamp(filename), failed: None }
+    }
+
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    // None when the file hasn't changed since it was last loaded or failed to load
+    pub fn poll(&mut self) -> Option<Result<Config, ConfigError>> {
+        // taken before reading so a write that finishes meanwhile is read again
+        let stamp = stamp(&self.filename);
+
+        if stamp == self.loaded || Some(stamp) == self.failed { return None }
+
+        let config = Config::load(&self.filename);
+
+        if config.is_ok() {
+            self.loaded = stamp;
+            self.failed = None;
+        } else {
+            self.failed = Some(stamp);
+        }
+
+        Some(config)
+    }
+}
+
+fn stamp(filename: &str) -> Stamp {
+    let metadata = std::fs::metadata(filename).ok()?;
+
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+fn validate_colors(detector: &str, colors: &ColorClass) -> Result<(), ConfigError> {
+    let invalid = |reason: String| Err(ConfigError::Invalid(detector.to_string(), reason));
+
+    if colors.include.is_empty() {
+        return invalid("colors must include at least one range".to_string());
+    }
+
+    let ordered = |r: &(f32, f32)| r.0 <= r.1;
+
+    for range in colors.include.iter().chain(&colors.exclude) {
+        let valid = match range {
+            ColorRange::Rgb(channels) => channels.iter().all(|(lower, upper)| lower <= upper),
+            ColorRange::Hsv { hue, saturation: a, value: b } |
+            ColorRange::Hsl { hue, saturation: a, lightness: b } =>
+                (0.0..=360.0).contains(&hue.from) && (0.0..=360.0).contains(&hue.to) && ordered(a) && ordered(b),
+            ColorRange::Lab { l, a, b } => ordered(l) && ordered(a) && ordered(b),
+            ColorRange::DeltaE { max_delta_e, .. } => *max_delta_e >= 0.0,
+        };
+
+        if !valid {
+            return invalid(format!("invalid color range {:?}", range));
+        }
+    }
+    Ok(())
+}
+
+fn validate_cleanup(detector: &str, cleanup: &[Cleanup]) -> Result<(), ConfigError> {
+    // larger kernels than this don't fit a downscaled frame's objects
+    const MAX_RADIUS: usize = 16;
+
+    for step in cleanup {
+        let radius = match step {
+            Cleanup::Median { radius, .. } => *radius,
+            Cleanup::Erode(se) | Cleanup::Dilate(se) | Cleanup::Open(se) | Cleanup::Close(se) => se.radius(),
+            Cleanup::FillHoles => 0,
+        };
+
+        if radius > MAX_RADIUS {
+            return Err(ConfigError::Invalid(detector.to_string(),
+                format!("cleanup step {:?} has a radius above {}", step, MAX_RADIUS)));
+        }
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background_adaptive::MixtureParameters;
+
+    fn parse_with(from: &str, to: &str) -> Result<Config, ConfigError> {
+        assert!(DEFAULT_CONFIG.contains(from), "{} isn't in the default config", from);
+
+        Config::parse(&DEFAULT_CONFIG.replacen(from, to, 1), "test")
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        let config = Config::default();
+
+        assert_eq!(config.projectiles.len(), 2);
+        assert!(config.player.is_none());
+    }
+
+    const PLAYER_SECTION: &str = r#"
+        [player]
+        colors.include = [ { rgb = [[200, 255], [0, 50], [0, 50]] } ]
+        min_area = 6
+        max_area = 40
+        gate = 9.21
+        max_misses = 5
+    "#;
+
+    #[test]
+    fn player_section_is_read_and_validated() {
+        let with_player = |from: &str, to: &str| {
+            Config::parse(&format!("{}\n{}", DEFAULT_CONFIG, PLAYER_SECTION.replacen(from, to, 1)), "test")
+        };
+
+        let player = with_player("", "").unwrap().player.unwrap();
+
+        assert_eq!((player.min_area, player.max_area, player.gate, player.max_misses), (6, 40, 9.21, 5));
+        assert!(player.cleanup.is_empty());
+
+        for (from, to) in [("gate = 9.21", "gate = 0.0"), ("gate = 9.21", "gate = nan"), ("max_misses = 5", "max_misses = 0"), ("min_area = 6", "min_area = 40")] {
+            assert!(matches!(with_player(from, to), Err(ConfigError::Invalid(..))), "{}", to);
+        }
+
+        assert!(matches!(with_player("max_misses = 5", ""), Err(ConfigError::Parse(..))));
+    }
+
+    #[test]
+    fn experimental_player_section_parses() {
+        let text = format!("{}\n{}", DEFAULT_CONFIG, include_str!("../detectors.player.toml"));
+
+        assert!(Config::parse(&text, "test").unwrap().player.is_some());
+    }
+
+    #[test]
+    fn subtraction_is_read_and_validated() {
+        let config = parse_with("subtraction = \"exact\"", "subtraction = { tolerance = [1, 2, 3] }").unwrap();
+        assert_eq!(config.background.subtraction, Subtraction::Tolerance([1, 2, 3]));
+
+        let changes = Config::default().changes(&config);
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0].starts_with("background:"), "{:?}", changes);
+
+        let config = parse_with("subtraction = \"exact\"", "subtraction = { statistical = { threshold = 3.0, min_variance = 4.0 } }").unwrap();
+        assert_eq!(config.background.subtraction, Subtraction::Statistical { threshold: 3.0, min_variance: 4.0 });
+
+        for invalid in ["{ statistical = { threshold = 0.0, min_variance = 4.0 } }", "{ statistical = { threshold = 3.0, min_variance = 0.0 } }"] {
+            let result = parse_with("subtraction = \"exact\"", &format!("subtraction = {}", invalid));
+            assert!(matches!(result, Err(ConfigError::Invalid(..))), "{}", invalid);
+        }
+
+        assert!(matches!(parse_with("subtraction = \"exact\"", "subtraction = \"approximate\""), Err(ConfigError::Parse(..))));
+    }
+
+    #[test]
+    fn learned_background_settings_are_read_and_validated() {
+        let config = Config::default();
+        assert_eq!(config.background.learned, AdaptiveConfig::default());
+
+        let config = parse_with("tolerance = [8, 8, 8]", "tolerance = [8, 8, 8]\nmixture = { learning_rate = 0.05 }").unwrap();
+        let mixture = config.background.learned.mixture.unwrap();
+
+        assert_eq!(mixture.learning_rate, 0.05);
+        assert_eq!(mixture.components, MixtureParameters::default().components);
+
+        assert!(matches!(parse_with("warmup_frames = 60", "warmup_frames = 0"), Err(ConfigError::Invalid(..))));
+
+        for invalid in [
+            "mixture = { components = 0 }",
+            "mixture = { learning_rate = 0.0 }",
+            "mixture = { background_weight = 1.5 }",
+            "mixture = { min_variance = -1.0 }",
+        ] {
+            let result = parse_with("tolerance = [8, 8, 8]", &format!("tolerance = [8, 8, 8]\n{}", invalid));
+            assert!(matches!(result, Err(ConfigError::Invalid(..))), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        for (from, to) in [
+            ("[tracker]", "[tracker]\nprocess_nosie = 1.0"),
+            ("subtraction = \"exact\"", "subtraction = { statistical = { threshold = 3.0, min_varience = 4.0 } }"),
+            ("tolerance = [8, 8, 8]", "tolerance = [8, 8, 8]\nwarmup = 3"),
+            ("tolerance = [8, 8, 8]", "tolerance = [8, 8, 8]\nmixture = { component = 3 }"),
+            ("[tracker]", "[trackers]\nprocess_noise = 1.0\nmeasurement_noise = 1.0\n[tracker]"),
+            ("gate = 9.21", "gate = 9.21\nconfirm = 2"),
+            ("horizon = 1.0", "horizon = 1.0\nimpact = 1.0"),
+            ("bbox_width = 17", "bbox_width = 17\nbbox_depth = 1"),
+            ("roundness = 0.25 }", "roundness = 0.25, min_circularty = 0.5 }"),
+            ("colors.include", "colors.exclud = []\ncolors.include"),
+            ("radius = 1, border = \"replicate\"", "radius = 1, border = \"replicate\", size = 3"),
+            ("[[projectile]]", "[[projectile]]\nspeed = 3"),
+        ] {
+            assert!(matches!(parse_with(from, to), Err(ConfigError::Parse(..))), "{}", to);
+        }
+    }
+
+    #[test]
+    fn watcher_retries_until_an_edit_loads() {
+        let filename = std::env::temp_dir().join(format!("bumper_robot_watcher_{}.toml", std::process::id()));
+        let filename = filename.to_str().unwrap();
+
+        std::fs::write(filename, DEFAULT_CONFIG).unwrap();
+
+        let mut watcher = ConfigWatcher::new(filename);
+        assert!(watcher.poll().is_none());
+
+        // caught halfway through writing
+        let half = &DEFAULT_CONFIG[..DEFAULT_CONFIG.len() / 2];
+        std::fs::write(filename, half).unwrap();
+
+        assert!(matches!(watcher.poll(), Some(Err(_))));
+        assert!(watcher.poll().is_none());
+
+        let edited = DEFAULT_CONFIG.replacen("impact_radius = 6.0", "impact_radius = 8.0", 1);
+        std::fs::write(filename, &edited).unwrap();
+
+        let config = watcher.poll().unwrap().unwrap();
+        assert_eq!(config.prediction.impact_radius, 8.0);
+        assert!(watcher.poll().is_none());
+
+        std::fs::remove_file(filename).unwrap();
+        assert!(matches!(watcher.poll(), Some(Err(ConfigError::Io(..)))));
+        assert!(watcher.poll().is_none());
+    }
+
+    #[test]
+    fn unknown_color_range_fields_are_rejected() {
+        let hsv = "{ hsv = { hue = { from = 340.0, to = 20.0 }, saturation = [0.6, 1.0], value = [0.4, 1.0] } }";
+
+        assert!(parse_with("{ rgb = [[0, 255], [0, 255], [58, 202]] }", hsv).is_ok());
+        assert!(parse_with("{ rgb = [[0, 255], [0, 255], [58, 202]] }", &hsv.replace("value = [0.4, 1.0]", "value = [0.4, 1.0], brightness = [0.0, 1.0]")).is_err());
+        assert!(parse_with("{ rgb = [[0, 255], [0, 255], [58, 202]] }", &hsv.replace("to = 20.0", "to = 20.0, by = 1.0")).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+use std::{fmt, marker::PhantomData, ops::{Add, Div}, str::FromStr};
+
+use crate::img::IMAGE_DOWNSCALE_FACTOR;
+
+
+// Coordinate spaces
+// Downscaled:       pixels of the active area after img::shrink
+// Active:           pixels of the active area
+// Window:           pixels of the game window
+// Screen:           pixels of the primary display
+// WindowNormalized: fractions of the game window's size, origin at its top left
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Downscaled {}
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Active {}
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Window {}
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Screen {}
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WindowNormalized {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rectangle<T, S> {
+    pub top:    T,
+    pub left:   T,
+    pub width:  T,
+    pub height: T,
+    space: PhantomData<S>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point<T, S> {
+    pub x: T,
+    pub y: T,
+    space: PhantomData<S>,
+}
+
+impl<T, S> Rectangle<T, S> {
+    pub const fn new(left: T, top: T, width: T, height: T) -> Self {
+        Rectangle { left, top, width, height, space: PhantomData }
+    }
+}
+
+impl<T: Copy + Add<Output = T> + Div<Output = T> + From<u8>, S> Rectangle<T, S> {
+    pub fn center(&self) -> Point<T, S> {
+        let two = T::from(2);
+
+        Point::new(self.left + self.width / two, self.top + self.height / two)
+    }
+}
+
+impl<T, S> Point<T, S> {
+    pub const fn new(x: T, y: T) -> Self {
+        Point { x, y, space: PhantomData }
+    }
+}
+
+// whitespace separated "left top width height"
+impl<T: fmt::Display, S> fmt::Display for Rectangle<T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {} {}", self.left, self.top, self.width, self.height)
+    }
+}
+
+impl<T: FromStr, S> FromStr for Rectangle<T, S> {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut values = s.split_whitespace().map(|v| v.parse::<T>().map_err(|_| ()));
+
+        let rectangle = Rectangle::new(
+            values.next().ok_or(())??,
+            values.next().ok_or(())??,
+            values.next().ok_or(())??,
+            values.next().ok_or(())??,
+        );
+
+        match values.next() {
+            Some(_) => Err(()),
+            None => Ok(rectangle),
+        }
+    }
+}
+
+// Conversions
+// None when the result doesn't fit in the target space
+
+pub fn downscaled_to_active(r: &Rectangle<usize, Downscaled>) -> Option<Rectangle<usize, Active>> {
+    const S: usize = IMAGE_DOWNSCALE_FACTOR;
+
+    Some(Rectangle::new(
+        r.left.checked_mul(S)?,
+        r.top.checked_mul(S)?,
+        r.width.checked_mul(S)?,
+        r.height.checked_mul(S)?,
+    ))
+}
+
+pub fn active_to_screen(r: &Rectangle<usize, Active>, active_area: &Rectangle<usize, Screen>) -> Option<Rectangle<usize, Screen>> {
+    Some(Rectangle::new(
+        active_area.left.checked_add(r.left)?,
+        active_area.top.checked_add(r.top)?,
+        r.width,
+        r.height,
+    ))
+}
+
+pub fn screen_to_window(r: &Rectangle<usize, Screen>, window: &Rectangle<usize, Screen>) -> Option<Rectangle<usize, Window>> {
+    Some(Rectangle::new(
+        r.left.checked_sub(window.left)?,
+        r.top.checked_sub(window.top)?,
+        r.width,
+        r.height,
+    ))
+}
+
+pub fn window_to_normalized(r: &Rectangle<usize, Window>, window: &Rectangle<usize, Screen>) -> Option<Rectangle<f32, WindowNormalized>> {
+    if window.width == 0 || window.height == 0 { return None }
+
+    let w = window.width as f32;
+    let h = window.height as f32;
+
+    Some(Rectangle::new(
+        r.left as f32 / w,
+        r.top as f32 / h,
+        r.width as f32 / w,
+        r.height as f32 / h,
+    ))
+}
+
+// truncates to whole pixels
+pub fn normalized_to_window(r: &Rectangle<f32, WindowNormalized>, window: &Rectangle<usize, Screen>) -> Option<Rectangle<usize, Window>> {
+    let to_pixels = |v: f32, size: usize| {
+        let v = v * size as f32;
+        if v.is_finite() && v >= 0.0 { Some(v as usize) } else { None }
+    };
+
+    Some(Rectangle::new(
+        to_pixels(r.left, window.width)?,
+        to_pixels(r.top, window.height)?,
+        to_pixels(r.width, window.width)?,
+        to_pixels(r.height, window.height)?,
+    ))
+}
+
+pub fn window_to_screen(r: &Rectangle<usize, Window>, window: &Rectangle<usize, Screen>) -> Option<Rectangle<usize, Screen>> {
+    Some(Rectangle::new(
+        window.left.checked_add(r.left)?,
+        window.top.checked_add(r.top)?,
+        r.width,
+        r.height,
+    ))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Rectangle<usize, Screen> = Rectangle::new(100, 50, 640, 480);
+
+    #[test]
+    fn downscaled_scales_up_to_active() {
+        const S: usize = IMAGE_DOWNSCALE_FACTOR;
+
+        let r = downscaled_to_active(&Rectangle::new(1, 2, 3, 4)).unwrap();
+        assert_eq!(r, Rectangle::new(S, 2 * S, 3 * S, 4 * S));
+
+        assert!(downscaled_to_active(&Rectangle::new(usize::MAX, 0, 1, 1)).is_none());
+    }
+
+    #[test]
+    fn screen_and_window_round_trip() {
+        let screen: Rectangle<usize, Screen> = Rectangle::new(110, 70, 5, 6);
+
+        let window = screen_to_window(&screen, &WINDOW).unwrap();
+        assert_eq!(window, Rectangle::new(10, 20, 5, 6));
+        assert_eq!(window_to_screen(&window, &WINDOW).unwrap(), screen);
+
+        // left of or above the window
+        assert!(screen_to_window(&Rectangle::new(99, 70, 5, 6), &WINDOW).is_none());
+        assert!(screen_to_window(&Rectangle::new(110, 49, 5, 6), &WINDOW).is_none());
+        assert!(window_to_screen(&Rectangle::new(usize::MAX, 0, 1, 1), &WINDOW).is_none());
+    }
+
+    #[test]
+    fn active_is_offset_by_the_active_area() {
+        let r = active_to_screen(&Rectangle::new(1, 2, 3, 4), &WINDOW).unwrap();
+        assert_eq!(r, Rectangle::new(101, 52, 3, 4));
+
+        assert!(active_to_screen(&Rectangle::new(usize::MAX, 0, 1, 1), &WINDOW).is_none());
+    }
+
+    #[test]
+    fn window_and_normalized_round_trip() {
+        let window: Rectangle<usize, Window> = Rectangle::new(160, 120, 320, 48);
+
+        let normalized = window_to_normalized(&window, &WINDOW).unwrap();
+        assert_eq!(normalized, Rectangle::new(0.25, 0.25, 0.5, 0.1));
+        assert_eq!(normalized_to_window(&normalized, &WINDOW).unwrap(), window);
+
+        // outside of the window to the left, or not a number
+        assert!(normalized_to_window(&Rectangle::new(-0.1, 0.0, 0.5, 0.5), &WINDOW).is_none());
+        assert!(normalized_to_window(&Rectangle::new(f32::NAN, 0.0, 0.5, 0.5), &WINDOW).is_none());
+        assert!(window_to_normalized(&window, &Rectangle::new(0, 0, 0, 480)).is_none());
+    }
+
+    #[test]
+    fn rectangles_parse_what_they_display() {
+        let r: Rectangle<usize, Screen> = Rectangle::new(1, 2, 3, 4);
+
+        assert_eq!(r.to_string().parse::<Rectangle<usize, Screen>>(), Ok(r));
+
+        for s in ["", "1 2 3", "1 2 3 4 5", "1 2 3 -4", "a b c d"] {
+            assert!(s.parse::<Rectangle<usize, Screen>>().is_err(), "{}", s);
+        }
+    }
+}
@@ -0,0 +1,649 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use crate::{config::{Config, HeadConfig, PlayerConfig, ProjectileConfig, TrackerConfig}, coordinates::{Downscaled, Point, Rectangle}, img::{self, Image, L8, Rgb8}, img_connected_components::{Connectivity, connected_components}, img_morphology, kalman::KalmanFilter};
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DetectionClass {
+    // the head the player should bump
+    TargetHead,
+    // the blue head
+    OtherHead,
+    // the character steered by the cursor
+    Player,
+    // named by its config
+    Projectile(String),
+}
+
+// "target_head", "other_head", "player" or "projectile:<name>"
+impl fmt::Display for DetectionClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DetectionClass::TargetHead => write!(f, "target_head"),
+            DetectionClass::OtherHead => write!(f, "other_head"),
+            DetectionClass::Player => write!(f, "player"),
+            DetectionClass::Projectile(name) => write!(f, "projectile:{}", name),
+        }
+    }
+}
+
+impl FromStr for DetectionClass {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "target_head" => Ok(DetectionClass::TargetHead),
+            "other_head" => Ok(DetectionClass::OtherHead),
+            "player" => Ok(DetectionClass::Player),
+            _ => s.strip_prefix("projectile:")
+                .filter(|name| !name.is_empty())
+                .map(|name| DetectionClass::Projectile(name.to_string()))
+                .ok_or(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Detection {
+    pub class: DetectionClass,
+    pub bbox: Rectangle<usize, Downscaled>,
+    pub centroid: Point<f32, Downscaled>,
+    // persistent across frames, projectiles get one once their track is confirmed
+    pub track_id: Option<u32>,
+    // in [0, 1]
+    pub confidence: f32,
+    // downscaled pixels per second, when a filter follows the detection:
+    // the heads and the player always, projectiles with their track id
+    pub velocity: Option<(f32, f32)>,
+}
+
+impl Detection {
+    // untracked, centered on the bounding box
+    pub fn new(class: DetectionClass, bbox: Rectangle<usize, Downscaled>, confidence: f32) -> Self {
+        let c = bbox.center();
+
+        Detection {
+            class,
+            centroid: Point::new(c.x as f32, c.y as f32),
+            bbox,
+            track_id: None,
+            confidence,
+            velocity: None,
+        }
+    }
+}
+
+// whitespace separated "<class> <bbox> <centroid x> <centroid y> <track id> <confidence> <velocity x> <velocity y>"
+// with "-" for a missing track id and velocity components
+impl fmt::Display for Detection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {:.2} {:.2} ", self.class, self.bbox, self.centroid.x, self.centroid.y)?;
+
+        match self.track_id {
+            Some(id) => write!(f, "{} ", id)?,
+            None => write!(f, "- ")?,
+        }
+
+        write!(f, "{:.3} ", self.confidence)?;
+
+        match self.velocity {
+            Some((x, y)) => write!(f, "{:.2} {:.2}", x, y),
+            None => write!(f, "- -"),
+        }
+    }
+}
+
+impl FromStr for Detection {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut values = s.split_whitespace();
+        let mut next = || values.next().ok_or(());
+
+        let class = next()?.parse()?;
+
+        let bbox = Rectangle::new(
+            next()?.parse().map_err(|_| ())?,
+            next()?.parse().map_err(|_| ())?,
+            next()?.parse().map_err(|_| ())?,
+            next()?.parse().map_err(|_| ())?,
+        );
+
+        let centroid = Point::new(next()?.parse().map_err(|_| ())?, next()?.parse().map_err(|_| ())?);
+
+        let track_id = match next()? {
+            "-" => None,
+            id => Some(id.parse().map_err(|_| ())?),
+        };
+
+        let confidence = next()?.parse().map_err(|_| ())?;
+
+        let velocity = match (next()?, next()?) {
+            ("-", "-") => None,
+            (x, y) => Some((x.parse().map_err(|_| ())?, y.parse().map_err(|_| ())?)),
+        };
+
+        if next().is_ok() { return Err(()) }
+
+        Ok(Detection { class, bbox, centroid, track_id, confidence, velocity })
+    }
+}
+
+// What detectors see of the current frame
+pub struct FrameContext<'a> {
+    // downscaled active area with the background zeroed
+    pub img: &'a Image<Rgb8>,
+    // 255 for foreground pixels
+    pub foreground: &'a Image<L8>,
+    // capture time
+    pub t: Duration,
+}
+
+pub trait Detector {
+    // unique among the robot's detectors
+    fn name(&self) -> &str;
+
+    // picks up changed parameters while keeping any tracking state
+    // false if the config no longer has this detector
+    fn reconfigure(&mut self, config: &Config) -> bool;
+
+    fn detect(&mut self, frame: &FrameContext) -> Vec<Detection>;
+}
+
+// Head detection first, then the player if configured, then projectiles in config order
+// detectors in previous with the same name are reused to keep their state
+pub fn build_detectors(config: &Config, mut previous: Vec<Box<dyn Detector>>) -> Vec<Box<dyn Detector>> {
+    let mut reuse = |name: &str| {
+        let i = previous.iter().position(|d| d.name() == name)?;
+        let mut detector = previous.swap_remove(i);
+
+        if detector.reconfigure(config) { Some(detector) } else { None }
+    };
+
+    let mut detectors = vec![
+        reuse(HEAD).unwrap_or_else(|| Box::new(HeadDetector::new(config)))
+    ];
+
+    if config.player.is_some() {
+        detectors.push(reuse(PLAYER).unwrap_or_else(|| Box::new(PlayerDetector::new(config))));
+    }
+
+    for p in &config.projectiles {
+        detectors.push(reuse(&p.name).unwrap_or_else(|| Box::new(ProjectileDetector::new(p.clone()))));
+    }
+    detectors
+}
+
+// projectiles can't use these names
+pub const HEAD: &str = "head";
+pub const PLAYER: &str = "player";
+
+// Tracks the largest blob of the head's color and mirrors it for the target head
+pub struct HeadDetector {
+    config: HeadConfig,
+    bound: Rectangle<usize, Downscaled>,
+    filter: KalmanFilter,
+    // capture time of the last update
+    t_last: Option<Duration>,
+}
+
+impl HeadDetector {
+    pub fn new(config: &Config) -> Self {
+        let mut filter = KalmanFilter::default();
+
+        filter.set_noise(config.tracker.process_noise, config.tracker.measurement_noise);
+
+        HeadDetector {
+            config: config.head.clone(),
+            filter,
+            bound: Rectangle::new(0, 0, 0, 0),
+            t_last: None,
+        }
+    }
+}
+
+impl Detector for HeadDetector {
+    fn name(&self) -> &str {
+        HEAD
+    }
+
+    fn reconfigure(&mut self, config: &Config) -> bool {
+        self.config = config.head.clone();
+        self.filter.set_noise(config.tracker.process_noise, config.tracker.measurement_noise);
+        true
+    }
+
+    // confidence is 0 when the head wasn't seen, the filter is then updated with the last bound
+    // so the head is held where it was last seen instead of drifting with its velocity
+    fn detect(&mut self, frame: &FrameContext) -> Vec<Detection> {
+        let config = &self.config;
+
+        let img = img::threshold(frame.img, &config.colors);
+
+        let img = img_morphology::cleanup(img, &config.cleanup);
+
+        // equal areas go to the first component in raster order, not to whichever was labeled last
+        let largest = connected_components(&img, Connectivity::Four, None).1
+            .into_iter()
+            .max_by(|a, b|{ a.area.cmp(&b.area).then(b.id.cmp(&a.id)) });
+
+        let confidence = if largest.is_some() { 1.0 } else { 0.0 };
+
+        if let Some(c) = largest {
+            self.bound = c.bounding_box();
+        }
+
+        let dt = self.t_last.map_or(Duration::ZERO, |t_last| frame.t.saturating_sub(t_last));
+        self.t_last = Some(frame.t);
+
+        self.filter.predict(dt.as_secs_f32());
+
+        let c = self.bound.center();
+
+        self.filter.update(&(c.x as f32, c.y as f32));
+
+        let p = self.filter.position();
+
+        // negative positions saturate to 0
+        self.bound = Rectangle::new(
+            (p.0 as usize).saturating_sub(config.bbox_width / 2),
+            (p.1 as usize).saturating_sub(config.bbox_height / 2),
+            config.bbox_width,
+            config.bbox_height,
+        );
+
+        let other = self.bound.clone();
+        let target = mirror_horizontal(&other, frame.img.width()); // target the non-blue head
+
+        let (vx, vy) = self.filter.velocity();
+
+        vec![
+            Detection { velocity: Some((-vx, vy)), ..Detection::new(DetectionClass::TargetHead, target, confidence) },
+            Detection { velocity: Some((vx, vy)), ..Detection::new(DetectionClass::OtherHead, other, confidence) },
+        ]
+    }
+}
+
+// Tracks the player's blob, preferring the one closest to the predicted position once seen
+pub struct PlayerDetector {
+    config: PlayerConfig,
+    tracker: TrackerConfig,
+    filter: KalmanFilter,
+    // size of the last detected bounding box
+    size: (usize, usize),
+    // capture time of the last update, None until the player was seen
+    t_last: Option<Duration>,
+    // consecutive frames without a blob within the gate
+    misses: usize,
+}
+
+impl PlayerDetector {
+    // config.player must be set
+    pub fn new(config: &Config) -> Self {
+        PlayerDetector {
+            config: config.player.clone().expect("PlayerDetector without a player config"),
+            tracker: config.tracker.clone(),
+            // replaced when the player is first seen
+            filter: KalmanFilter::default(),
+            size: (0, 0),
+            t_last: None,
+            misses: 0,
+        }
+    }
+}
+
+impl Detector for PlayerDetector {
+    fn name(&self) -> &str {
+        PLAYER
+    }
+
+    fn reconfigure(&mut self, config: &Config) -> bool {
+        match &config.player {
+            Some(player) => {
+                self.config = player.clone();
+                self.tracker = config.tracker.clone();
+                self.filter.set_noise(self.tracker.process_noise, self.tracker.measurement_noise);
+                true
+            },
+            None => false,
+        }
+    }
+
+    // nothing until the player is first seen, then the filtered position every frame
+    // confidence is 0 when the player wasn't seen and the position is only predicted,
+    // nothing again after config.max_misses such frames in a row
+    fn detect(&mut self, frame: &FrameContext) -> Vec<Detection> {
+        let config = &self.config;
+
+        let img = img::threshold(frame.img, &config.colors);
+
+        let img = img_morphology::cleanup(img, &config.cleanup);
+
+        let candidates = connected_components(&img, Connectivity::Four, None).1
+            .into_iter()
+            .filter(|c| c.area > config.min_area && c.area < config.max_area);
+
+        let seen = match self.t_last {
+            // equal areas go to the first component in raster order
+            None => candidates.max_by(|a, b| a.area.cmp(&b.area).then(b.id.cmp(&a.id))),
+            Some(t_last) => {
+                self.filter.predict(frame.t.saturating_sub(t_last).as_secs_f32());
+
+                let filter = &self.filter;
+
+                candidates
+                    .map(|c| (filter.distance(&c.centroid), c))
+                    .filter(|(d, _)| *d <= config.gate)
+                    .min_by(|a, b| a.0.total_cmp(&b.0))
+                    .map(|(_, c)| c)
+            },
+        };
+
+        match (&seen, self.t_last) {
+            (None, None) => return vec![],
+            // starts at rest where first seen
+            (Some(c), None) => {
+                self.filter = KalmanFilter::at(c.centroid, self.tracker.process_noise, self.tracker.measurement_noise)
+            },
+            (Some(c), Some(_)) => self.filter.update(&c.centroid),
+            (None, Some(_)) => {
+                self.misses += 1;
+
+                // lost, the largest blob is taken again
+                if self.misses > config.max_misses {
+                    self.misses = 0;
+                    self.t_last = None;
+                    return vec![];
+                }
+            },
+        }
+        self.t_last = Some(frame.t);
+
+        let confidence = match &seen {
+            Some(c) => {
+                let bbox = c.bounding_box();
+
+                self.size = (bbox.width, bbox.height);
+                self.misses = 0;
+                1.0
+            },
+            None => 0.0,
+        };
+
+        let (x, y) = self.filter.position();
+
+        // negative positions saturate to 0
+        let bbox = Rectangle::new(
+            (x - self.size.0 as f32 / 2.0).round() as usize,
+            (y - self.size.1 as f32 / 2.0).round() as usize,
+            self.size.0,
+            self.size.1,
+        );
+
+        vec![Detection {
+            centroid: Point::new(x, y),
+            velocity: Some(self.filter.velocity()),
+            ..Detection::new(DetectionClass::Player, bbox, confidence)
+        }]
+    }
+}
+
+// clamped to the left edge
+fn mirror_horizontal(r: &Rectangle<usize, Downscaled>, width: usize) -> Rectangle<usize, Downscaled> {
+    Rectangle::new(width.saturating_sub(r.left + r.width), r.top, r.width, r.height)
+}
+
+// Blobs of the projectile's colors that pass its shape filter
+pub struct ProjectileDetector {
+    config: ProjectileConfig,
+}
+
+impl ProjectileDetector {
+    pub fn new(config: ProjectileConfig) -> Self {
+        ProjectileDetector { config }
+    }
+}
+
+impl Detector for ProjectileDetector {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn reconfigure(&mut self, config: &Config) -> bool {
+        match config.projectiles.iter().find(|p| p.name == self.config.name) {
+            Some(p) => { self.config = p.clone(); true },
+            None => false,
+        }
+    }
+
+    // confidence falls off linearly from a square bounding box to the roundness limit
+    fn detect(&mut self, frame: &FrameContext) -> Vec<Detection> {
+        let shape = &self.config.shape;
+
+        let img = img::threshold(frame.img, &self.config.colors);
+
+        let img = img_morphology::cleanup(img, &self.config.cleanup);
+
+        connected_components(&img, Connectivity::Four, Some(frame.img)).1
+            .into_iter()
+            .filter_map(|c|{
+                let width = c.right - c.left;
+                let height = c.bottom - c.top;
+                let ratio = width as f32 / height as f32;
+
+                let pass =
+                    c.area > shape.min_area &&
+                    c.area < shape.max_area &&
+                    ratio > (1.0 - shape.roundness) &&
+                    ratio < (1.0 + shape.roundness) &&
+                    shape.min_circularity.is_none_or(|min| c.circularity > min);
+
+                pass.then(|| Detection {
+                    centroid: c.centroid_point(),
+                    ..Detection::new(
+                        DetectionClass::Projectile(self.config.name.clone()),
+                        c.bounding_box(),
+                        (1.0 - (ratio - 1.0).abs() / shape.roundness).clamp(0.0, 1.0))
+                })
+            })
+            .collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: usize = 200;
+    const HEIGHT: usize = 40;
+
+    fn player(max_misses: usize) -> PlayerDetector {
+        let config = Config {
+            player: Some(toml::from_str(&format!(r#"
+                colors.include = [ {{ rgb = [[200, 255], [0, 50], [0, 50]] }} ]
+                min_area = 2
+                max_area = 100
+                gate = 9.21
+                max_misses = {}
+            "#, max_misses)).unwrap()),
+            ..Config::default()
+        };
+
+        PlayerDetector::new(&config)
+    }
+
+    // red squares given by their top left corner and size
+    fn frame(squares: &[(usize, usize, usize)]) -> Image<Rgb8> {
+        let mut img: Image<Rgb8> = Image::new(WIDTH, HEIGHT);
+
+        for &(left, top, size) in squares {
+            for y in top..top + size {
+                for x in left..left + size {
+                    img.data_mut()[3 * (y * WIDTH + x)] = 255;
+                }
+            }
+        }
+        img
+    }
+
+    fn head() -> HeadDetector {
+        let config = Config {
+            head: toml::from_str(r#"
+                colors.include = [ { rgb = [[200, 255], [0, 50], [0, 50]] } ]
+                bbox_width = 5
+                bbox_height = 5
+            "#).unwrap(),
+            ..Config::default()
+        };
+
+        HeadDetector::new(&config)
+    }
+
+    fn detect_head(detector: &mut HeadDetector, img: &Image<Rgb8>, i: u64) -> Vec<Detection> {
+        let foreground: Image<L8> = Image::new(WIDTH, HEIGHT);
+
+        detector.detect(&FrameContext { img, foreground: &foreground, t: Duration::from_millis(16 * i) })
+    }
+
+    fn detect(detector: &mut PlayerDetector, img: &Image<Rgb8>, i: u64) -> Vec<Detection> {
+        let foreground: Image<L8> = Image::new(WIDTH, HEIGHT);
+
+        detector.detect(&FrameContext { img, foreground: &foreground, t: Duration::from_millis(16 * i) })
+    }
+
+    #[test]
+    fn largest_blob_is_taken_first() {
+        let mut detector = player(5);
+
+        assert!(detect(&mut detector, &frame(&[]), 0).is_empty());
+
+        let detections = detect(&mut detector, &frame(&[(10, 10, 3), (50, 10, 5)]), 1);
+
+        assert_eq!(detections.len(), 1);
+        assert_eq!(detections[0].confidence, 1.0);
+        assert_eq!((detections[0].centroid.x, detections[0].centroid.y), (52.0, 12.0));
+    }
+
+    #[test]
+    fn equal_blobs_go_to_the_first_in_raster_order() {
+        let mut detector = player(5);
+
+        let detections = detect(&mut detector, &frame(&[(50, 20, 4), (10, 20, 4), (100, 5, 4)]), 0);
+
+        assert_eq!((detections[0].centroid.x, detections[0].centroid.y), (101.5, 6.5));
+    }
+
+    #[test]
+    fn size_follows_the_component_bounding_box() {
+        let mut detector = player(5);
+        let img = frame(&[(10, 10, 5)]);
+
+        let mask = img::threshold(&img, &detector.config.colors);
+        let expected = connected_components(&mask, Connectivity::Four, None).1[0].bounding_box();
+
+        let detections = detect(&mut detector, &img, 0);
+
+        assert_eq!((detections[0].bbox.width, detections[0].bbox.height), (expected.width, expected.height));
+    }
+
+    #[test]
+    fn blobs_outside_the_gate_are_ignored() {
+        let mut detector = player(5);
+
+        for i in 0..10 {
+            detect(&mut detector, &frame(&[(10, 10, 5)]), i);
+        }
+
+        // a larger blob far away is not the player, the nearer smaller one is
+        let detections = detect(&mut detector, &frame(&[(150, 10, 8), (11, 10, 4)]), 10);
+
+        assert_eq!(detections[0].confidence, 1.0);
+        assert!(detections[0].centroid.x < 20.0, "{}", detections[0].centroid.x);
+
+        // and alone it is a miss
+        let detections = detect(&mut detector, &frame(&[(150, 10, 8)]), 11);
+
+        assert_eq!(detections[0].confidence, 0.0);
+        assert!(detections[0].centroid.x < 20.0, "{}", detections[0].centroid.x);
+    }
+
+    #[test]
+    fn player_is_looked_for_anew_after_max_misses() {
+        let mut detector = player(2);
+
+        detect(&mut detector, &frame(&[(10, 10, 5)]), 0);
+
+        for i in 1..=2 {
+            let detections = detect(&mut detector, &frame(&[]), i);
+
+            assert_eq!(detections.len(), 1);
+            assert_eq!(detections[0].confidence, 0.0);
+        }
+        assert!(detect(&mut detector, &frame(&[]), 3).is_empty());
+
+        // re-acquired anywhere
+        let detections = detect(&mut detector, &frame(&[(150, 10, 5)]), 4);
+
+        assert_eq!(detections[0].confidence, 1.0);
+        assert_eq!(detections[0].centroid.x, 152.0);
+    }
+
+    #[test]
+    fn head_slows_down_on_a_miss() {
+        let mut detector = head();
+
+        for i in 0..20 {
+            detect_head(&mut detector, &frame(&[(10 + i as usize, 10, 5)]), i);
+        }
+
+        let mut last = detect_head(&mut detector, &frame(&[]), 20);
+
+        for i in 21..30 {
+            let detections = detect_head(&mut detector, &frame(&[]), i);
+
+            assert_eq!(detections.len(), 2);
+            assert!(detections.iter().all(|d| d.confidence == 0.0));
+
+            let (other, last_other) = (&detections[1], &last[1]);
+
+            assert!(other.velocity.unwrap().0 < last_other.velocity.unwrap().0);
+            assert!(other.bbox.left <= last_other.bbox.left + 1, "{} from {}", other.bbox.left, last_other.bbox.left);
+            assert_eq!(other.bbox.top, last_other.bbox.top);
+
+            last = detections;
+        }
+    }
+
+    #[test]
+    fn head_is_reacquired_after_a_miss() {
+        let mut detector = head();
+
+        for i in 0..10 {
+            detect_head(&mut detector, &frame(&[(10, 10, 5)]), i);
+        }
+        for i in 10..15 {
+            detect_head(&mut detector, &frame(&[]), i);
+        }
+
+        let mut left = 0;
+
+        for i in 15..30 {
+            let detections = detect_head(&mut detector, &frame(&[(150, 20, 5)]), i);
+
+            assert!(detections.iter().all(|d| d.confidence == 1.0));
+
+            let (target, other) = (&detections[0].bbox, &detections[1].bbox);
+
+            assert_eq!(target.left, WIDTH - other.left - other.width);
+            assert_eq!(target.top, other.top);
+
+            // the filter smooths the jump over a few frames
+            if left < 150 {
+                assert!(other.left > left, "{} from {}", other.left, left);
+            }
+            left = other.left;
+        }
+        assert!(left >= 150, "{}", left);
+    }
+}
@@ -0,0 +1,101 @@
+use std::{fmt, time::{Duration, Instant}};
+
+use crate::{coordinates::{Downscaled, Rectangle}, detector::{Detection, DetectionClass}, input::MouseCommand, prediction::Prediction};
+
+
+// Everything the robot made of one frame
+#[derive(Debug, Clone)]
+pub struct FrameResult {
+    // capture time
+    pub t: Duration,
+    // in detector order
+    pub detections: Vec<Detection>,
+    // of every tracked projectile
+    pub predictions: Vec<Prediction>,
+    // the response to this frame, None if there was nothing to do
+    pub action: Option<MouseCommand>,
+    // wall time of each processing stage in order
+    pub timings: Vec<(String, Duration)>,
+}
+
+impl FrameResult {
+    pub fn new(t: Duration) -> Self {
+        FrameResult { t, detections: vec![], predictions: vec![], action: None, timings: vec![] }
+    }
+
+    pub fn of_class<'a>(&'a self, class: &'a DetectionClass) -> impl Iterator<Item = &'a Detection> {
+        self.detections.iter().filter(move |d| &d.class == class)
+    }
+
+    pub fn rectangles(&self) -> Vec<Rectangle<usize, Downscaled>> {
+        self.detections.iter().map(|d| d.bbox.clone()).collect()
+    }
+
+    // records the time since start under stage and returns the time now
+    pub fn time(&mut self, stage: &str, start: Instant) -> Instant {
+        let now = Instant::now();
+
+        self.timings.push((stage.to_string(), now - start));
+
+        now
+    }
+}
+
+// timings differ between runs over the same frames and are left out
+impl PartialEq for FrameResult {
+    fn eq(&self, other: &Self) -> bool {
+        self.t == other.t &&
+        self.detections == other.detections &&
+        self.predictions == other.predictions &&
+        self.action == other.action
+    }
+}
+
+// "<t> <action>" followed by one indented line per detection, one per prediction and one with the timings
+impl fmt::Display for FrameResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.3}s ", self.t.as_secs_f32())?;
+
+        match &self.action {
+            Some(action) => writeln!(f, "{}", action)?,
+            None => writeln!(f, "no action")?,
+        }
+
+        for d in &self.detections {
+            writeln!(f, "  {}", d)?;
+        }
+
+        for p in &self.predictions {
+            writeln!(f, "  {}", p)?;
+        }
+
+        write!(f, "  timings:")?;
+
+        for (stage, duration) in &self.timings {
+            write!(f, " {} {:.2}ms", stage, duration.as_secs_f32() * 1000.0)?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equality_ignores_timings() {
+        let mut a = FrameResult::new(Duration::from_millis(5));
+        a.action = Some(MouseCommand::Press);
+
+        let mut b = a.clone();
+
+        a.timings.push(("shrink".to_string(), Duration::from_micros(10)));
+        b.timings.push(("shrink".to_string(), Duration::from_micros(20)));
+
+        assert_eq!(a, b);
+
+        b.action = None;
+        assert_ne!(a, b);
+    }
+}
@@ -1,154 +1,345 @@
-use std::collections::HashMap;
+use std::marker::PhantomData;
 
 use image::{ColorType, imageops, io::Reader};
+use serde::Deserialize;
+
+use crate::img_color::ColorClass;
 
 
 pub const IMAGE_DOWNSCALE_FACTOR: usize = 8;
 
 
-#[derive(Debug, Clone)]
-pub struct Rectangle<T> {
-    pub top:    T,
-    pub left:   T,
-    pub width:  T,
-    pub height: T,
+// Pixel formats
+pub trait PixelFormat {
+    type Channel: Copy + Default;
+    const CHANNELS: usize;
+}
+
+// formats that can be written to an image file
+pub trait ColorFormat: PixelFormat<Channel = u8> {
+    const COLOR_TYPE: ColorType;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rgb8 {}
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bgr8 {}
+// 8 bit luminance, masks are 0/255
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum L8 {}
+// connected component ids
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Label {}
+
+impl PixelFormat for Rgb8 {
+    type Channel = u8;
+    const CHANNELS: usize = 3;
+}
+
+impl PixelFormat for Bgr8 {
+    type Channel = u8;
+    const CHANNELS: usize = 3;
+}
+
+impl PixelFormat for L8 {
+    type Channel = u8;
+    const CHANNELS: usize = 1;
+}
+
+impl PixelFormat for Label {
+    type Channel = u32;
+    const CHANNELS: usize = 1;
 }
 
-pub trait SaveBuffer {
-    fn save(&self, width: usize, height: usize, color_type: ColorType, filename: &str);
+impl ColorFormat for Rgb8 { const COLOR_TYPE: ColorType = ColorType::Rgb8; }
+impl ColorFormat for Bgr8 { const COLOR_TYPE: ColorType = ColorType::Bgr8; }
+impl ColorFormat for L8 { const COLOR_TYPE: ColorType = ColorType::L8; }
+
+// Row major with interleaved channels
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image<P: PixelFormat> {
+    width: usize,
+    height: usize,
+    data: Vec<P::Channel>,
+    format: PhantomData<P>,
+}
+
+impl<P: PixelFormat> Image<P> {
+    pub fn new(width: usize, height: usize) -> Self {
+        Image {
+            width,
+            height,
+            data: vec![P::Channel::default(); width * height * P::CHANNELS],
+            format: PhantomData,
+        }
+    }
+
+    // None if the buffer size doesn't match the dimensions
+    pub fn from_raw(width: usize, height: usize, data: Vec<P::Channel>) -> Option<Self> {
+        if data.len() != width * height * P::CHANNELS { return None }
+
+        Some(Image { width, height, data, format: PhantomData })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn data(&self) -> &[P::Channel] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [P::Channel] {
+        &mut self.data
+    }
+
+    pub fn into_raw(self) -> Vec<P::Channel> {
+        self.data
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> &[P::Channel] {
+        let idx = P::CHANNELS * (y * self.width + x);
+        &self.data[idx..idx + P::CHANNELS]
+    }
+
+    pub fn pixels(&self) -> std::slice::ChunksExact<'_, P::Channel> {
+        self.data.chunks_exact(P::CHANNELS)
+    }
+
+    // same buffer in another format with the same layout
+    fn reinterpret<Q: PixelFormat<Channel = P::Channel>>(self) -> Image<Q> {
+        debug_assert_eq!(P::CHANNELS, Q::CHANNELS);
+
+        Image { width: self.width, height: self.height, data: self.data, format: PhantomData }
+    }
 }
 
-impl SaveBuffer for Vec<u8> {
-    fn save(&self, width: usize, height: usize, color_type: ColorType, filename: &str) {
-        image::save_buffer(filename, self,
-            width as u32,
-            height as u32,
-            color_type)
+impl<P: ColorFormat> Image<P> {
+    pub fn save(&self, filename: &str) {
+        self.try_save(filename)
             .unwrap_or_else(|e|{panic!("Unable to save image {} : {}", filename, e)})
     }
+
+    pub fn try_save(&self, filename: &str) -> image::ImageResult<()> {
+        image::save_buffer(filename, &self.data,
+            self.width as u32,
+            self.height as u32,
+            P::COLOR_TYPE)
+    }
 }
 
-pub fn load(filename: &str) -> Option<(Vec<u8>, usize, usize)> {
+pub fn load(filename: &str) -> Option<Image<Bgr8>> {
     let reader = Reader::open(filename).ok()?;
     let image = reader.decode().ok()?;
     let buffer = image.into_bgr8();
     let width = buffer.width() as usize;
     let height = buffer.height() as usize;
 
-    Some((buffer.into_raw(), width, height))
+    Image::from_raw(width, height, buffer.into_raw())
+}
+
+pub fn load_luma(filename: &str) -> Option<Image<L8>> {
+    let reader = Reader::open(filename).ok()?;
+    let image = reader.decode().ok()?;
+    let buffer = image.into_luma8();
+    let width = buffer.width() as usize;
+    let height = buffer.height() as usize;
+
+    Image::from_raw(width, height, buffer.into_raw())
 }
 
-// RGB -> RGB
-pub fn shrink(img: Vec<u8>, width: usize, height: usize, scale: usize) -> (usize, usize, Vec<u8>) {
-    let r = image::RgbImage::from_raw(width as u32, height as u32, img)
+pub fn shrink(img: Image<Rgb8>, scale: usize) -> Image<Rgb8> {
+    let (width, height) = (img.width, img.height);
+
+    let r = image::RgbImage::from_raw(width as u32, height as u32, img.into_raw())
         .expect("shrink: failed to convert img type");
 
     let r = imageops::thumbnail(&r, (width / scale) as u32, (height / scale) as u32);
 
-    (r.width() as usize, r.height() as usize, r.into_raw())
+    Image::from_raw(r.width() as usize, r.height() as usize, r.into_raw())
+        .expect("shrink: unexpected thumbnail size")
 }
 
-// RGB -> L8
-pub fn threshold_blue(img: &[u8], lower: u8, upper: u8) -> Vec<u8> {
-    let mut out = Vec::with_capacity(img.len() / 3);
+// one pass over the image for the whole class
+pub fn threshold(img: &Image<Rgb8>, class: &ColorClass) -> Image<L8> {
+    let out = img.pixels()
+        .map(|p| if class.contains(p) { u8::MAX } else { u8::MIN })
+        .collect();
 
-    for p in img.chunks_exact(3) {
-        match p {
-            [_, _, b] => {
-                out.push( if *b > lower && *b < upper { u8::MAX } else { u8::MIN } )
-            },
-            _ => unreachable!("Invalid RGB image")
-        }
-    }
-    out
+    Image::from_raw(img.width, img.height, out).unwrap()
+}
+
+// How pixels outside the image are sampled
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BorderMode {
+    // aaa|abcd|ddd
+    Replicate,
+    // cba|abcd|dcb
+    Reflect,
+    // vvv|abcd|vvv
+    Constant(u8),
 }
 
-// RGB -> L8
-pub fn threshold(img: &[u8], thresholds: &[(u8, u8); 3]) -> Vec<u8> {
-    let mut out = Vec::with_capacity(img.len() / 3);
+// index into 0..len for i, None for a constant border
+fn border_index(i: isize, len: usize, border: BorderMode) -> Option<usize> {
+    let n = len as isize;
 
-    for p in img.chunks_exact(3) {
-        match p {
-            [r, g, b] => {
-                out.push(
-                    if  *r >= thresholds[0].0 && *r <= thresholds[0].1 &&
-                        *g >= thresholds[1].0 && *g <= thresholds[1].1 &&
-                        *b >= thresholds[2].0 && *b <= thresholds[2].1
-                    {
-                        u8::MAX
-                    } else {
-                        u8::MIN
-                    }
-                )
-            },
-            _ => unreachable!("Invalid RGB image")
-        }
+    if (0..n).contains(&i) { return Some(i as usize) }
+
+    match border {
+        BorderMode::Replicate => Some(i.clamp(0, n - 1) as usize),
+        BorderMode::Reflect => {
+            let period = 2 * n;
+            let i = i.rem_euclid(period);
+            Some(if i < n { i } else { period - 1 - i } as usize)
+        },
+        BorderMode::Constant(_) => None,
     }
-    out
 }
 
-// L8 -> L8
-pub fn median3x3(img: &[u8], width: usize, height: usize) -> Vec<u8> {
-    let mut out = vec![0; img.len()];
+// (2 radius + 1) x (2 radius + 1) median of each channel
+// keeps a histogram of the window that slides along each row
+pub fn median<P: PixelFormat<Channel = u8>>(img: &Image<P>, radius: usize, border: BorderMode) -> Image<P> {
+    let (width, height) = (img.width, img.height);
+    let channels = P::CHANNELS;
 
-    for y in 1..height-1 {
-        for x in 1..width-1 {
-            let idx = y * width + x;
+    let mut out = Image::new(width, height);
 
-            let mut samples = [
-                img[idx - width - 1], img[idx - width], img[idx - width + 1],
-                img[idx         - 1], img[idx        ], img[idx         + 1],
-                img[idx + width - 1], img[idx + width], img[idx + width + 1]];
+    let r = radius as isize;
+    let rank = ((2 * radius + 1) * (2 * radius + 1)) / 2;
 
-            samples.sort_unstable();
-
-            out[idx] = samples[4];
+    let sample = |x: isize, y: isize, c: usize| {
+        match (border_index(x, width, border), border_index(y, height, border), border) {
+            (Some(x), Some(y), _) => img.data[(y * width + x) * channels + c],
+            (_, _, BorderMode::Constant(v)) => v,
+            _ => unreachable!("median: border index outside the image"),
         }
-    }
-    out
-}
+    };
 
-// a -> a
-// separate for each channel
-// would be more appropriate as a triple if that is what is being passed in
-pub fn mode(images: &[Vec<u8>]) -> Option<Vec<u8>> {
-    let mut out = vec![0; images.first()?.len()];
+    for c in 0..channels {
+        for y in 0..height as isize {
+            let mut histogram = [0usize; 256];
 
-    for p in 0..out.len() {
-        let mut frequencies = HashMap::new();
+            for wy in y - r..=y + r {
+                for wx in -r..=r {
+                    histogram[sample(wx, wy, c) as usize] += 1;
+                }
+            }
 
-        for image in images {
-            frequencies.entry(image[p]).and_modify(|x|*x+=1).or_insert(1);
-        }
+            for x in 0..width as isize {
+                if x > 0 {
+                    for wy in y - r..=y + r {
+                        histogram[sample(x - r - 1, wy, c) as usize] -= 1;
+                        histogram[sample(x + r, wy, c) as usize] += 1;
+                    }
+                }
+
+                let mut count = 0;
+                let value = histogram.iter()
+                    .position(|&h| { count += h; count > rank })
+                    .expect("median: empty window");
 
-        let mut mode = 0;
-        let mut max_frequency = 0;
-        for (value, frequency) in frequencies.drain() {
-            if frequency > max_frequency {
-                max_frequency = frequency;
-                mode = value;
+                out.data[(y as usize * width + x as usize) * channels + c] = value as u8;
             }
         }
-        out[p] = mode;
     }
-    Some(out)
+    out
 }
 
-pub fn bgr_to_rgb(image: &mut [u8]) {
-    for p in image.chunks_exact_mut(3) {
+pub fn bgr_to_rgb(image: Image<Bgr8>) -> Image<Rgb8> {
+    let mut image = image.reinterpret::<Rgb8>();
+
+    for p in image.data.chunks_exact_mut(3) {
         match p {
             [r, _, b] => { std::mem::swap(r, b) },
             _ => unreachable!("Incorrect size bgr image")
         }
     }
+    image
 }
 
-// RGB -> RGB -> RGB
-pub fn remove_background(img: &[u8], back: &[u8]) -> Vec<u8> {
-    let mut out = Vec::with_capacity(img.len());
+// zeroes every pixel outside the mask, None if their sizes differ
+pub fn apply_mask(img: &Image<Rgb8>, mask: &Image<L8>) -> Option<Image<Rgb8>> {
+    if img.width != mask.width || img.height != mask.height { return None }
+
+    let mut out = Vec::with_capacity(img.data.len());
 
-    for (&a, &b) in img.iter().zip(back) {
-        out.push(if a == b { 0 } else { a });
+    for (p, &m) in img.pixels().zip(&mask.data) {
+        if m == 0 { out.extend_from_slice(&[0; 3]) } else { out.extend_from_slice(p) }
+    }
+    Image::from_raw(img.width, img.height, out)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 10 20 30
+    // 40 50 60
+    // 70 80 90
+    fn square() -> Image<L8> {
+        Image::from_raw(3, 3, vec![10, 20, 30, 40, 50, 60, 70, 80, 90]).unwrap()
+    }
+
+    #[test]
+    fn border_indices_follow_the_mode() {
+        for i in 0..4 {
+            assert_eq!(border_index(i, 4, BorderMode::Constant(7)), Some(i as usize));
+        }
+
+        assert_eq!(border_index(-3, 4, BorderMode::Replicate), Some(0));
+        assert_eq!(border_index(6, 4, BorderMode::Replicate), Some(3));
+
+        let reflect = [-5, -2, -1, 4, 5, 8].map(|i| border_index(i, 4, BorderMode::Reflect));
+        assert_eq!(reflect, [Some(3), Some(1), Some(0), Some(3), Some(2), Some(0)]);
+
+        assert_eq!(border_index(-1, 4, BorderMode::Constant(7)), None);
+        assert_eq!(border_index(4, 4, BorderMode::Constant(7)), None);
+    }
+
+    #[test]
+    fn replicate_repeats_the_edge_pixels() {
+        // corner window 10 10 20 10 10 20 40 40 50, edge window 10 20 30 10 20 30 40 50 60
+        assert_eq!(median(&square(), 1, BorderMode::Replicate).data(), &[
+            20, 30, 30,
+            40, 50, 60,
+            70, 70, 80,
+        ]);
+    }
+
+    #[test]
+    fn constant_pads_with_the_value() {
+        assert_eq!(median(&square(), 1, BorderMode::Constant(0)).data(), &[
+            0, 20, 0,
+            20, 50, 30,
+            0, 50, 0,
+        ]);
+        assert_eq!(median(&square(), 1, BorderMode::Constant(255)).data(), &[
+            255, 50, 255,
+            70, 50, 80,
+            255, 80, 255,
+        ]);
+    }
+
+    #[test]
+    fn reflect_mirrors_beyond_the_edge_pixels() {
+        // a single row, windows of 5 at the left edge: 10 10 10 20 30 replicated, 20 10 10 20 30 reflected
+        let row: Image<L8> = Image::from_raw(4, 1, vec![10, 20, 30, 40]).unwrap();
+
+        assert_eq!(median(&row, 2, BorderMode::Replicate).data(), &[10, 20, 30, 40]);
+        assert_eq!(median(&row, 2, BorderMode::Reflect).data(), &[20, 20, 30, 30]);
+    }
+
+    #[test]
+    fn channels_are_filtered_separately() {
+        let img: Image<Rgb8> = Image::from_raw(3, 1, vec![0, 90, 5,  10, 80, 5,  20, 70, 200]).unwrap();
+
+        assert_eq!(median(&img, 1, BorderMode::Replicate).data(), &[0, 90, 5,  10, 80, 5,  20, 70, 200]);
     }
-    out
 }
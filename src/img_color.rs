@@ -0,0 +1,282 @@
+use serde::Deserialize;
+
+use crate::img::{Image, PixelFormat, Rgb8};
+
+
+// Color spaces, with f32 channels
+// Hsv: hue in degrees [0, 360), saturation and value in [0, 1]
+// Hsl: hue in degrees [0, 360), saturation and lightness in [0, 1]
+// Lab: CIELAB under D65, L in [0, 100], a and b roughly in [-128, 127]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hsv {}
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hsl {}
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lab {}
+
+impl PixelFormat for Hsv {
+    type Channel = f32;
+    const CHANNELS: usize = 3;
+}
+
+impl PixelFormat for Hsl {
+    type Channel = f32;
+    const CHANNELS: usize = 3;
+}
+
+impl PixelFormat for Lab {
+    type Channel = f32;
+    const CHANNELS: usize = 3;
+}
+
+// Pixel conversions
+
+// hue, max and min of the rgb channels in [0, 1]
+fn hue_max_min(rgb: &[u8]) -> (f32, f32, f32) {
+    let [r, g, b] = [rgb[0] as f32 / 255.0, rgb[1] as f32 / 255.0, rgb[2] as f32 / 255.0];
+
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let d = max - min;
+
+    let hue = if d == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / d).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / d + 2.0)
+    } else {
+        60.0 * ((r - g) / d + 4.0)
+    };
+
+    (hue, max, min)
+}
+
+pub fn rgb_to_hsv(rgb: &[u8]) -> [f32; 3] {
+    let (h, max, min) = hue_max_min(rgb);
+
+    let s = if max == 0.0 { 0.0 } else { (max - min) / max };
+
+    [h, s, max]
+}
+
+pub fn rgb_to_hsl(rgb: &[u8]) -> [f32; 3] {
+    let (h, max, min) = hue_max_min(rgb);
+
+    let l = (max + min) / 2.0;
+    let d = max - min;
+
+    let s = if d == 0.0 { 0.0 } else { d / (1.0 - (2.0 * l - 1.0).abs()) };
+
+    [h, s, l]
+}
+
+pub fn rgb_to_lab(rgb: &[u8]) -> [f32; 3] {
+    // sRGB to linear
+    let linear = |v: u8| {
+        let v = v as f32 / 255.0;
+        if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+    };
+
+    let [r, g, b] = [linear(rgb[0]), linear(rgb[1]), linear(rgb[2])];
+
+    // XYZ relative to the D65 white point
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y =  0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+
+    let f = |t: f32| {
+        const DELTA: f32 = 6.0 / 29.0;
+        if t > DELTA * DELTA * DELTA { t.cbrt() } else { t / (3.0 * DELTA * DELTA) + 4.0 / 29.0 }
+    };
+
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+// CIE76
+pub fn delta_e(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum::<f32>().sqrt()
+}
+
+// Image conversions
+
+fn convert<P: PixelFormat<Channel = f32>>(img: &Image<Rgb8>, f: fn(&[u8]) -> [f32; 3]) -> Image<P> {
+    let data = img.pixels().flat_map(f).collect();
+
+    Image::from_raw(img.width(), img.height(), data).unwrap()
+}
+
+pub fn to_hsv(img: &Image<Rgb8>) -> Image<Hsv> {
+    convert(img, rgb_to_hsv)
+}
+
+pub fn to_hsl(img: &Image<Rgb8>) -> Image<Hsl> {
+    convert(img, rgb_to_hsl)
+}
+
+pub fn to_lab(img: &Image<Rgb8>) -> Image<Lab> {
+    convert(img, rgb_to_lab)
+}
+
+// Ranges are inclusive
+
+// degrees, wraps around through 0 when from > to, e.g. 330 to 30 for reds
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HueRange {
+    pub from: f32,
+    pub to: f32,
+}
+
+impl HueRange {
+    pub fn contains(&self, hue: f32) -> bool {
+        if self.from <= self.to {
+            hue >= self.from && hue <= self.to
+        } else {
+            hue >= self.from || hue <= self.to
+        }
+    }
+}
+
+fn within(v: f32, range: &(f32, f32)) -> bool {
+    v >= range.0 && v <= range.1
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum ColorRange {
+    Rgb([(u8, u8); 3]),
+    Hsv { hue: HueRange, saturation: (f32, f32), value: (f32, f32) },
+    Hsl { hue: HueRange, saturation: (f32, f32), lightness: (f32, f32) },
+    Lab { l: (f32, f32), a: (f32, f32), b: (f32, f32) },
+    // within max_delta_e of a reference Lab color
+    DeltaE { reference: [f32; 3], max_delta_e: f32 },
+}
+
+impl ColorRange {
+    pub fn contains(&self, rgb: &[u8]) -> bool {
+        match self {
+            ColorRange::Rgb(ranges) => rgb.iter().zip(ranges)
+                .all(|(&v, &(lower, upper))| v >= lower && v <= upper),
+            ColorRange::Hsv { hue, saturation, value } => {
+                let [h, s, v] = rgb_to_hsv(rgb);
+                hue.contains(h) && within(s, saturation) && within(v, value)
+            },
+            ColorRange::Hsl { hue, saturation, lightness } => {
+                let [h, s, l] = rgb_to_hsl(rgb);
+                hue.contains(h) && within(s, saturation) && within(l, lightness)
+            },
+            ColorRange::Lab { l, a, b } => {
+                let lab = rgb_to_lab(rgb);
+                within(lab[0], l) && within(lab[1], a) && within(lab[2], b)
+            },
+            ColorRange::DeltaE { reference, max_delta_e } => {
+                delta_e(&rgb_to_lab(rgb), reference) <= *max_delta_e
+            },
+        }
+    }
+}
+
+// Union of the included ranges minus the union of the excluded ones
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColorClass {
+    pub include: Vec<ColorRange>,
+    #[serde(default)]
+    pub exclude: Vec<ColorRange>,
+}
+
+impl ColorClass {
+    pub fn new(include: Vec<ColorRange>, exclude: Vec<ColorRange>) -> Self {
+        ColorClass { include, exclude }
+    }
+
+    pub fn contains(&self, rgb: &[u8]) -> bool {
+        self.include.iter().any(|r| r.contains(rgb)) &&
+        !self.exclude.iter().any(|r| r.contains(rgb))
+    }
+}
+
+impl From<ColorRange> for ColorClass {
+    fn from(range: ColorRange) -> Self {
+        ColorClass::new(vec![range], vec![])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3], tolerance: f32) {
+        assert!(actual.iter().zip(&expected).all(|(a, e)| (a - e).abs() <= tolerance),
+            "{:?} is not within {} of {:?}", actual, tolerance, expected);
+    }
+
+    #[test]
+    fn hsv_of_known_colors() {
+        assert_close(rgb_to_hsv(&[255, 0, 0]), [0.0, 1.0, 1.0], 1e-4);
+        assert_close(rgb_to_hsv(&[0, 255, 0]), [120.0, 1.0, 1.0], 1e-4);
+        assert_close(rgb_to_hsv(&[0, 0, 255]), [240.0, 1.0, 1.0], 1e-4);
+        assert_close(rgb_to_hsv(&[255, 0, 255]), [300.0, 1.0, 1.0], 1e-4);
+        assert_close(rgb_to_hsv(&[128, 128, 128]), [0.0, 0.0, 128.0 / 255.0], 1e-4);
+        assert_close(rgb_to_hsv(&[255, 255, 255]), [0.0, 0.0, 1.0], 1e-4);
+        assert_close(rgb_to_hsv(&[0, 0, 0]), [0.0, 0.0, 0.0], 1e-4);
+    }
+
+    #[test]
+    fn hsl_of_known_colors() {
+        assert_close(rgb_to_hsl(&[255, 0, 0]), [0.0, 1.0, 0.5], 1e-4);
+        assert_close(rgb_to_hsl(&[0, 255, 0]), [120.0, 1.0, 0.5], 1e-4);
+        assert_close(rgb_to_hsl(&[0, 0, 255]), [240.0, 1.0, 0.5], 1e-4);
+        assert_close(rgb_to_hsl(&[255, 128, 128]), [0.0, 1.0, 383.0 / 510.0], 1e-4);
+        assert_close(rgb_to_hsl(&[128, 128, 128]), [0.0, 0.0, 128.0 / 255.0], 1e-4);
+        assert_close(rgb_to_hsl(&[255, 255, 255]), [0.0, 0.0, 1.0], 1e-4);
+        assert_close(rgb_to_hsl(&[0, 0, 0]), [0.0, 0.0, 0.0], 1e-4);
+    }
+
+    #[test]
+    fn lab_of_known_colors() {
+        // reference values for sRGB under D65
+        assert_close(rgb_to_lab(&[255, 0, 0]), [53.24, 80.09, 67.20], 0.05);
+        assert_close(rgb_to_lab(&[0, 255, 0]), [87.73, -86.18, 83.18], 0.05);
+        assert_close(rgb_to_lab(&[0, 0, 255]), [32.30, 79.19, -107.86], 0.05);
+        assert_close(rgb_to_lab(&[128, 128, 128]), [53.59, 0.0, 0.0], 0.05);
+        assert_close(rgb_to_lab(&[255, 255, 255]), [100.0, 0.0, 0.0], 0.05);
+        assert_close(rgb_to_lab(&[0, 0, 0]), [0.0, 0.0, 0.0], 1e-4);
+    }
+
+    #[test]
+    fn hue_ranges_wrap_around_through_0() {
+        let reds = HueRange { from: 340.0, to: 20.0 };
+
+        assert!([340.0, 350.0, 359.9, 0.0, 10.0, 20.0].iter().all(|&h| reds.contains(h)));
+        assert!([339.9, 20.1, 180.0].iter().all(|&h| !reds.contains(h)));
+
+        let greens = HueRange { from: 100.0, to: 140.0 };
+
+        assert!(greens.contains(100.0) && greens.contains(140.0));
+        assert!(!greens.contains(0.0) && !greens.contains(350.0));
+    }
+
+    #[test]
+    fn excluded_colors_take_precedence() {
+        let everything = ColorRange::Rgb([(0, 255); 3]);
+        let reds = ColorRange::Hsv {
+            hue: HueRange { from: 340.0, to: 20.0 },
+            saturation: (0.5, 1.0),
+            value: (0.5, 1.0),
+        };
+
+        let class = ColorClass::new(vec![everything], vec![reds]);
+
+        assert!(!class.contains(&[255, 0, 0]));
+        assert!(!class.contains(&[255, 0, 40]));
+        assert!(class.contains(&[0, 255, 0]));
+        assert!(class.contains(&[128, 128, 128]));
+
+        // nothing is included without include ranges
+        assert!(!ColorClass::new(vec![], vec![]).contains(&[255, 0, 0]));
+    }
+}
@@ -1,14 +1,32 @@
-use std::{collections::VecDeque, ops::Sub};
+use std::{f32::consts::{PI, SQRT_2}, ops::Sub};
 
-use crate::img::Rectangle;
+use crate::{coordinates::{Downscaled, Point, Rectangle}, img::{Image, L8, Label, Rgb8}};
 
 
-// everything in this file assumes L8 image input
+// everything in this file treats non zero L8 pixels as foreground
 
-const UNCOLORED_BACKGROUND: u8 = u8::MIN;
-const COLORED_BACKGROUND: u8 = 1;
-const UNCOLORED_REGION: u8 = u8::MAX;
+pub const BACKGROUND_LABEL: u32 = 0;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Connectivity {
+    // edge neighbors
+    Four,
+    // edge and corner neighbors
+    Eight,
+}
+
+impl Connectivity {
+    // background regions have to be labeled with the other connectivity
+    // so they can't leak diagonally through the foreground
+    pub fn dual(self) -> Self {
+        match self {
+            Connectivity::Four => Connectivity::Eight,
+            Connectivity::Eight => Connectivity::Four,
+        }
+    }
+}
+
+// right and bottom are inclusive
 #[derive(Clone)]
 pub struct Component<T> {
     pub left: T,
@@ -16,13 +34,31 @@ pub struct Component<T> {
     pub right: T,
     pub bottom: T,
     pub area: T,
-    pub id: u8,
+    // label in the label image
+    pub id: u32,
+
+    // pixel centers, (x, y)
+    pub centroid: (f32, f32),
+    // central second order moments divided by the area
+    pub mu20: f32,
+    pub mu02: f32,
+    pub mu11: f32,
+    // angle of the major axis in radians from the x axis, y pointing down
+    pub orientation: f32,
+    // Crofton estimate from the boundary crossings in 4 directions, 2 pi r for a disk
+    pub perimeter: f32,
+    // 4 pi area / perimeter^2, up to 1 for a disk, about 0.9 for a square and lower the less compact
+    pub circularity: f32,
+    // enclosed background regions
+    pub holes: usize,
+    // per channel, None when connected_components isn't given a color image
+    pub mean_color: Option<[f32; 3]>,
 }
 
 impl Component<usize> {
     // private
     // initializes to extreme values for use with min() and max()
-    fn new(id: u8) -> Self {
+    fn new(id: u32) -> Self {
         Component {
             left: usize::MAX,
             top: usize::MAX,
@@ -30,104 +66,471 @@ impl Component<usize> {
             bottom: usize::MIN,
             area: 0,
             id,
+            centroid: (0.0, 0.0),
+            mu20: 0.0,
+            mu02: 0.0,
+            mu11: 0.0,
+            orientation: 0.0,
+            perimeter: 0.0,
+            circularity: 0.0,
+            holes: 0,
+            mean_color: None,
         }
     }
+
+    fn add(&mut self, x: usize, y: usize) {
+        self.area += 1;
+        self.left   = self.left.min(x);
+        self.top    = self.top.min(y);
+        self.right  = self.right.max(x);
+        self.bottom = self.bottom.max(y);
+    }
 }
 
+// components are only looked for in downscaled images
 impl<T: Copy + Sub<Output = T>> Component<T> {
-    pub fn bounding_box(&self) -> Rectangle<T> {
-        Rectangle {
-            left: self.left,
-            top: self.top,
-            width: self.right - self.left,
-            height: self.bottom - self.top,
-        }
+    pub fn bounding_box(&self) -> Rectangle<T, Downscaled> {
+        Rectangle::new(
+            self.left,
+            self.top,
+            self.right - self.left,
+            self.bottom - self.top,
+        )
+    }
+
+    pub fn centroid_point(&self) -> Point<f32, Downscaled> {
+        Point::new(self.centroid.0, self.centroid.1)
     }
 }
 
-fn neighbors(img: &[u8], width: usize, height: usize, x: usize, y: usize) -> Vec<(usize, usize)> {
-    let idx = y * width + x;
+// raw sums of pixel coordinates
+#[derive(Clone, Default)]
+struct Moments {
+    x: f64,
+    y: f64,
+    xx: f64,
+    yy: f64,
+    xy: f64,
+}
 
-    let mut r = vec![];
+impl Moments {
+    fn add(&mut self, x: usize, y: usize) {
+        let (x, y) = (x as f64, y as f64);
 
-    if x > 0 {
-        let v1 = img[idx - 1];
-        if v1 == UNCOLORED_REGION || v1 == UNCOLORED_BACKGROUND { r.push((x - 1, y)) }
+        self.x  += x;
+        self.y  += y;
+        self.xx += x * x;
+        self.yy += y * y;
+        self.xy += x * y;
     }
-    if x < width - 1 {
-        let v1 = img[idx + 1];
-        if v1 == UNCOLORED_REGION || v1 == UNCOLORED_BACKGROUND { r.push((x + 1, y)) }
+
+    fn finish(&self, c: &mut Component<usize>) {
+        let area = c.area as f64;
+
+        let (cx, cy) = (self.x / area, self.y / area);
+
+        let mu20 = self.xx / area - cx * cx;
+        let mu02 = self.yy / area - cy * cy;
+        let mu11 = self.xy / area - cx * cy;
+
+        c.centroid = (cx as f32, cy as f32);
+        c.mu20 = mu20 as f32;
+        c.mu02 = mu02 as f32;
+        c.mu11 = mu11 as f32;
+        c.orientation = (0.5 * (2.0 * mu11).atan2(mu20 - mu02)) as f32;
+    }
+}
+
+// union-find over provisional labels
+struct Equivalences {
+    parent: Vec<u32>,
+}
+
+impl Equivalences {
+    fn make(&mut self) -> u32 {
+        let label = self.parent.len() as u32;
+        self.parent.push(label);
+        label
     }
-    if y > 0 {
-        let v1 = img[idx - width];
-        if v1 == UNCOLORED_REGION || v1 == UNCOLORED_BACKGROUND { r.push((x , y - 1)) }
+
+    fn find(&mut self, mut a: u32) -> u32 {
+        while self.parent[a as usize] != a {
+            // path halving
+            let grandparent = self.parent[self.parent[a as usize] as usize];
+            self.parent[a as usize] = grandparent;
+            a = grandparent;
+        }
+        a
     }
-    if y < height - 1 {
-        let v1 = img[idx + width];
-        if v1 == UNCOLORED_REGION || v1 == UNCOLORED_BACKGROUND { r.push((x , y + 1)) }
+
+    // keeps the smaller root so labels stay in raster order
+    fn union(&mut self, a: u32, b: u32) -> u32 {
+        let a = self.find(a);
+        let b = self.find(b);
+        let (root, child) = if a < b { (a, b) } else { (b, a) };
+        self.parent[child as usize] = root;
+        root
+    }
+}
+
+// Two pass labeling
+// returns the label image (BACKGROUND_LABEL for background, 1.. for each component
+// in raster order of their first pixel) and the components ordered by label
+// colors are measured over img, of the mask's size, when given
+pub fn connected_components(mask: &Image<L8>, connectivity: Connectivity, img: Option<&Image<Rgb8>>) -> (Image<Label>, Vec<Component<usize>>) {
+    let (labels, mut components, moments) = label(mask, connectivity);
+
+    for (c, m) in components.iter_mut().zip(&moments) {
+        m.finish(c);
+    }
+
+    measure_perimeters(&labels, &mut components);
+
+    count_holes(mask, &labels, connectivity, &mut components);
+
+    if let Some(img) = img {
+        measure_color(&labels, img, &mut components);
     }
-    r
+
+    (labels, components)
 }
 
-fn dfs_cc(img: &mut[u8], q: &mut VecDeque<(usize, usize)>, width: usize, height: usize, id: u8, x: usize, y: usize) -> Component<usize>{
-    let mut stack: Vec<(usize, usize)> = Vec::new();
+// bounding boxes and areas only
+fn label(mask: &Image<L8>, connectivity: Connectivity) -> (Image<Label>, Vec<Component<usize>>, Vec<Moments>) {
+    let (width, height) = (mask.width(), mask.height());
+    let mask = mask.data();
+
+    let mut labels: Image<Label> = Image::new(width, height);
 
-    let mut component: Component<usize> = Component::new(id);
+    // provisional label 0 stands for the background
+    let mut equivalences = Equivalences { parent: vec![BACKGROUND_LABEL] };
 
-    stack.push((x, y));
+    {
+        let labels = labels.data_mut();
 
-    while let Some((x, y)) = stack.pop() {
-        let idx = y * width + x;
+        for y in 0..height {
+            for x in 0..width {
+                let idx = y * width + x;
 
-        match img[idx] {
-            UNCOLORED_REGION => {
-                img[idx] = id;
+                if mask[idx] == 0 { continue }
 
-                component.area += 1;
-                component.left   = component.left.min(x);
-                component.top    = component.top.min(y);
-                component.right  = component.right.max(x);
-                component.bottom = component.bottom.max(y);
+                // previously visited neighbors: west, north west, north, north east
+                let west  = if x > 0 { labels[idx - 1] } else { BACKGROUND_LABEL };
+                let north = if y > 0 { labels[idx - width] } else { BACKGROUND_LABEL };
+                let neighbors = match connectivity {
+                    Connectivity::Four => [west, north, BACKGROUND_LABEL],
+                    // the other neighbors touch north, so they were already joined with it
+                    Connectivity::Eight if north != BACKGROUND_LABEL => [north, BACKGROUND_LABEL, BACKGROUND_LABEL],
+                    Connectivity::Eight => [
+                        west,
+                        if x > 0 && y > 0 { labels[idx - width - 1] } else { BACKGROUND_LABEL },
+                        if x + 1 < width && y > 0 { labels[idx - width + 1] } else { BACKGROUND_LABEL },
+                    ],
+                };
 
-                for neighbor in neighbors(img, width, height, x, y) {
-                    stack.push(neighbor)
+                let mut label = BACKGROUND_LABEL;
+
+                for neighbor in neighbors {
+                    if neighbor == BACKGROUND_LABEL || neighbor == label { continue }
+
+                    label = if label == BACKGROUND_LABEL {
+                        neighbor
+                    } else {
+                        equivalences.union(label, neighbor)
+                    };
                 }
-            },
-            UNCOLORED_BACKGROUND => { q.push_back((x, y)) },
-            COLORED_BACKGROUND => {},
-            other_id if other_id == id => {},
-            _ => unreachable!("dfs_cc: encountered another region while coloring")
+
+                labels[idx] = if label == BACKGROUND_LABEL { equivalences.make() } else { label };
+            }
         }
     }
-    component
+
+    // provisional root -> final label
+    let mut final_labels = vec![BACKGROUND_LABEL; equivalences.parent.len()];
+    let mut components: Vec<Component<usize>> = vec![];
+    let mut moments: Vec<Moments> = vec![];
+
+    let labels_data = labels.data_mut();
+
+    for y in 0..height {
+        for x in 0..width {
+            let idx = y * width + x;
+
+            let provisional = labels_data[idx];
+            if provisional == BACKGROUND_LABEL { continue }
+
+            let root = equivalences.find(provisional) as usize;
+
+            if final_labels[root] == BACKGROUND_LABEL {
+                let id = components.len() as u32 + 1;
+                final_labels[root] = id;
+                components.push(Component::new(id));
+                moments.push(Moments::default());
+            }
+
+            let id = final_labels[root];
+            labels_data[idx] = id;
+
+            components[(id - 1) as usize].add(x, y);
+            moments[(id - 1) as usize].add(x, y);
+        }
+    }
+
+    (labels, components, moments)
 }
 
-// recolors the binary (0/255) input image
-// 0 -> 1 (background)
-// 255 -> 2.. for each component
-pub fn connected_components(img: &mut [u8], width: usize, height: usize) -> Vec<Component<usize>> {
-    let mut id: u8 = COLORED_BACKGROUND;
-    let mut q: VecDeque<(usize, usize)> = VecDeque::new();
-    let mut components = vec![];
-
-    q.push_back((0, 0));
-
-    while let Some((x, y)) = q.pop_front() {
-        let idx = y * width + x;
-
-        match img[idx] {
-            UNCOLORED_REGION => {
-                id += 1;
-                components.push(dfs_cc(img, &mut q, width, height, id, x, y))
-            },
-            UNCOLORED_BACKGROUND => {
-                img[idx] = COLORED_BACKGROUND;
-                for neighbor in neighbors(img, width, height, x, y) {
-                    q.push_back(neighbor)
-                }
-            },
-            _ => {}
+// perimeter and circularity
+// Crofton: pi/8 (crossings along rows + columns + crossings along both diagonals / sqrt 2)
+// where every neighbor with another label is a crossing, diagonal lines being 1/sqrt 2 apart
+fn measure_perimeters(labels: &Image<Label>, components: &mut [Component<usize>]) {
+    let (width, height) = (labels.width() as isize, labels.height() as isize);
+    let data = labels.data();
+
+    // the image border counts as a different label
+    let differs = |x: isize, y: isize, id: u32| {
+        x < 0 || y < 0 || x >= width || y >= height || data[(y * width + x) as usize] != id
+    };
+
+    let mut crossings = vec![(0usize, 0usize); components.len()];
+
+    for y in 0..height {
+        for x in 0..width {
+            let id = data[(y * width + x) as usize];
+            if id == BACKGROUND_LABEL { continue }
+
+            let (axis, diagonal) = &mut crossings[(id - 1) as usize];
+
+            for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                *axis += differs(x + dx, y + dy, id) as usize;
+            }
+            for (dx, dy) in [(-1, -1), (1, -1), (-1, 1), (1, 1)] {
+                *diagonal += differs(x + dx, y + dy, id) as usize;
+            }
+        }
+    }
+
+    for (c, &(axis, diagonal)) in components.iter_mut().zip(&crossings) {
+        c.perimeter = PI / 8.0 * (axis as f32 + diagonal as f32 / SQRT_2);
+
+        // digital shapes this small can come out rounder than a disk
+        c.circularity = (4.0 * PI * c.area as f32 / (c.perimeter * c.perimeter)).min(1.0);
+    }
+}
+
+// A background region that doesn't touch the image border is a hole in the component
+// directly above its first pixel in raster order.
+// Any component nested inside the hole lies below that row.
+fn count_holes(mask: &Image<L8>, labels: &Image<Label>, connectivity: Connectivity, components: &mut [Component<usize>]) {
+    if components.is_empty() { return }
+
+    let (width, height) = (mask.width(), mask.height());
+
+    let inverted = Image::from_raw(width, height,
+        mask.data().iter().map(|&p| if p == 0 { u8::MAX } else { u8::MIN }).collect())
+        .expect("count_holes: unexpected mask size");
+
+    let (background_labels, background, _) = label(&inverted, connectivity.dual());
+
+    for region in background {
+        if region.left == 0 || region.top == 0 || region.right + 1 == width || region.bottom + 1 == height { continue }
+
+        let first = (region.left..=region.right)
+            .map(|x| region.top * width + x)
+            .find(|&idx| background_labels.data()[idx] == region.id)
+            .expect("count_holes: region without pixels");
+
+        let owner = labels.data()[first - width];
+
+        if owner != BACKGROUND_LABEL {
+            components[(owner - 1) as usize].holes += 1;
+        }
+    }
+}
+
+// per channel mean of img over each component
+fn measure_color(labels: &Image<Label>, img: &Image<Rgb8>, components: &mut [Component<usize>]) {
+    let mut sums = vec![[0u64; 3]; components.len()];
+
+    for (&id, p) in labels.data().iter().zip(img.pixels()) {
+        if id == BACKGROUND_LABEL { continue }
+
+        let sum = &mut sums[(id - 1) as usize];
+
+        for (s, &v) in sum.iter_mut().zip(p) {
+            *s += v as u64;
+        }
+    }
+
+    for (c, sum) in components.iter_mut().zip(&sums) {
+        let area = c.area as f32;
+
+        c.mean_color = Some([sum[0] as f32 / area, sum[1] as f32 / area, sum[2] as f32 / area]);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // '#' is foreground
+    fn mask(rows: &[&str]) -> Image<L8> {
+        let data = rows.iter()
+            .flat_map(|row| row.chars().map(|c| if c == '#' { u8::MAX } else { u8::MIN }))
+            .collect();
+
+        Image::from_raw(rows[0].len(), rows.len(), data).unwrap()
+    }
+
+    #[test]
+    fn diagonal_neighbors_only_join_with_eight_connectivity() {
+        let diagonal = mask(&[
+            "#..",
+            ".#.",
+            "..#",
+        ]);
+
+        assert_eq!(connected_components(&diagonal, Connectivity::Four, None).1.len(), 3);
+        assert_eq!(connected_components(&diagonal, Connectivity::Eight, None).1.len(), 1);
+    }
+
+    #[test]
+    fn u_shapes_merge_into_one_label() {
+        let w = mask(&[
+            "#.#.#",
+            "#.#.#",
+            "#####",
+        ]);
+
+        for connectivity in [Connectivity::Four, Connectivity::Eight] {
+            let (labels, components) = connected_components(&w, connectivity, None);
+
+            assert_eq!(components.len(), 1);
+            assert_eq!(components[0].area, 11);
+            assert_eq!((components[0].left, components[0].top, components[0].right, components[0].bottom), (0, 0, 4, 2));
+            assert!(labels.data().iter().zip(w.data()).all(|(&l, &m)| (l == 1) == (m != 0)));
         }
     }
-    components
+
+    #[test]
+    fn labels_are_in_raster_order_of_first_pixels() {
+        let blobs = mask(&[
+            "...##",
+            "#....",
+            "#.#..",
+        ]);
+
+        let (labels, components) = connected_components(&blobs, Connectivity::Four, None);
+
+        assert_eq!(components.iter().map(|c| (c.id, c.left, c.top)).collect::<Vec<_>>(), vec![(1, 3, 0), (2, 0, 1), (3, 2, 2)]);
+        assert_eq!(labels.data()[5], 2);
+    }
+
+    #[test]
+    fn holes_use_the_dual_connectivity() {
+        let ring = mask(&[
+            "#####",
+            "#..##",
+            "#.#.#",
+            "#####",
+        ]);
+
+        let (_, components) = connected_components(&ring, Connectivity::Four, None);
+
+        assert_eq!(components.len(), 1);
+        // the two background pixels touching diagonally are one 8-connected hole
+        assert_eq!(components[0].holes, 1);
+
+        let diamond = mask(&[
+            ".....",
+            "..#..",
+            ".#.#.",
+            "..#..",
+            ".....",
+        ]);
+
+        // four pixels the background leaks between
+        let (_, components) = connected_components(&diamond, Connectivity::Four, None);
+        assert_eq!(components.len(), 4);
+        assert!(components.iter().all(|c| c.holes == 0));
+
+        // one ring around a 4-connected hole
+        let (_, components) = connected_components(&diamond, Connectivity::Eight, None);
+        assert_eq!(components.len(), 1);
+        assert_eq!(components[0].holes, 1);
+    }
+
+    #[test]
+    fn holes_belong_to_the_enclosing_component() {
+        let nested = mask(&[
+            "#######",
+            "#.....#",
+            "#.###.#",
+            "#.#.#.#",
+            "#.###.#",
+            "#.....#",
+            "#######",
+        ]);
+
+        let (_, components) = connected_components(&nested, Connectivity::Four, None);
+
+        assert_eq!(components.iter().map(|c| c.holes).collect::<Vec<_>>(), vec![1, 1]);
+    }
+
+    #[test]
+    fn borders_are_not_holes() {
+        let open = mask(&[
+            "###",
+            "#..",
+            "###",
+        ]);
+
+        assert_eq!(connected_components(&open, Connectivity::Four, None).1[0].holes, 0);
+    }
+
+    #[test]
+    fn disks_are_rounder_than_squares() {
+        let (size, radius) = (30, 10);
+
+        let disk = Image::from_raw(size, size, (0..size * size)
+            .map(|i| {
+                let (x, y) = ((i % size) as isize - 15, (i / size) as isize - 15);
+                if x * x + y * y <= radius * radius { u8::MAX } else { u8::MIN }
+            })
+            .collect()).unwrap();
+
+        let disk = connected_components(&disk, Connectivity::Four, None).1.remove(0);
+
+        // same area
+        let side = (disk.area as f32).sqrt().round() as usize;
+
+        let square = Image::from_raw(size, size, (0..size * size)
+            .map(|i| if i % size < side && i / size < side { u8::MAX } else { u8::MIN })
+            .collect()).unwrap();
+
+        let square = connected_components(&square, Connectivity::Four, None).1.remove(0);
+
+        let exact = 2.0 * PI * radius as f32;
+
+        assert!((disk.perimeter - exact).abs() < 0.05 * exact, "disk perimeter {}", disk.perimeter);
+        assert!(disk.circularity > 0.9, "disk {}", disk.circularity);
+        assert!((0.85..0.95).contains(&square.circularity), "square {}", square.circularity);
+        assert!(disk.circularity > square.circularity + 0.02, "disk {} square {}", disk.circularity, square.circularity);
+    }
+
+    #[test]
+    fn mean_color_is_measured_when_given_an_image() {
+        let blobs = mask(&[
+            "##..#",
+        ]);
+        let img = Image::from_raw(5, 1, vec![
+            10, 20, 30,  30, 40, 50,  0, 0, 0,  0, 0, 0,  7, 8, 9,
+        ]).unwrap();
+
+        let (_, components) = connected_components(&blobs, Connectivity::Four, Some(&img));
+
+        assert_eq!(components[0].mean_color, Some([20.0, 30.0, 40.0]));
+        assert_eq!(components[1].mean_color, Some([7.0, 8.0, 9.0]));
+        assert_eq!(connected_components(&blobs, Connectivity::Four, None).1[0].mean_color, None);
+    }
 }
@@ -0,0 +1,334 @@
+use serde::Deserialize;
+
+use crate::img::{self, BorderMode, Image, L8};
+
+
+// Flat structuring elements centered on the pixel, sized by their radius
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StructuringElement {
+    // (2r + 1) x (2r + 1)
+    Square(usize),
+    // horizontal and vertical arms of length r
+    Cross(usize),
+    // offsets within euclidean distance r
+    Disk(usize),
+}
+
+impl StructuringElement {
+    pub fn radius(&self) -> usize {
+        match *self {
+            StructuringElement::Square(r) | StructuringElement::Cross(r) | StructuringElement::Disk(r) => r,
+        }
+    }
+
+    pub fn contains(&self, dx: isize, dy: isize) -> bool {
+        let r = self.radius() as isize;
+
+        if dx.abs() > r || dy.abs() > r { return false }
+
+        match self {
+            StructuringElement::Square(_) => true,
+            StructuringElement::Cross(_) => dx == 0 || dy == 0,
+            StructuringElement::Disk(_) => dx * dx + dy * dy <= r * r,
+        }
+    }
+
+    fn offsets(&self) -> Vec<(isize, isize)> {
+        let r = self.radius() as isize;
+
+        (-r..=r)
+            .flat_map(|dy| (-r..=r).map(move |dx| (dx, dy)))
+            .filter(|&(dx, dy)| self.contains(dx, dy))
+            .collect()
+    }
+}
+
+// Masks are cleaned up by applying each step in order
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Cleanup {
+    Median { radius: usize, border: BorderMode },
+    Erode(StructuringElement),
+    Dilate(StructuringElement),
+    Open(StructuringElement),
+    Close(StructuringElement),
+    FillHoles,
+}
+
+pub fn cleanup(img: Image<L8>, chain: &[Cleanup]) -> Image<L8> {
+    chain.iter().fold(img, |img, step| match *step {
+        Cleanup::Median { radius, border } => img::median(&img, radius, border),
+        Cleanup::Erode(se) => erode(&img, &se),
+        Cleanup::Dilate(se) => dilate(&img, &se),
+        Cleanup::Open(se) => open(&img, &se),
+        Cleanup::Close(se) => close(&img, &se),
+        Cleanup::FillHoles => fill_holes(&img),
+    })
+}
+
+// grayscale min/max over the structuring element
+// pixels outside the image are ignored, so the border isn't eroded away
+// 0/255 masks give the binary operations
+fn rank(img: &Image<L8>, se: &StructuringElement, pick: fn(u8, u8) -> u8, initial: u8) -> Image<L8> {
+    let (width, height) = (img.width(), img.height());
+    let data = img.data();
+
+    let offsets = se.offsets();
+
+    let mut out = Image::new(width, height);
+
+    for y in 0..height {
+        for x in 0..width {
+            let mut value = initial;
+
+            for &(dx, dy) in &offsets {
+                let sx = x as isize + dx;
+                let sy = y as isize + dy;
+
+                if sx < 0 || sy < 0 || sx >= width as isize || sy >= height as isize { continue }
+
+                value = pick(value, data[sy as usize * width + sx as usize]);
+            }
+            out.data_mut()[y * width + x] = value;
+        }
+    }
+    out
+}
+
+pub fn erode(img: &Image<L8>, se: &StructuringElement) -> Image<L8> {
+    rank(img, se, u8::min, u8::MAX)
+}
+
+pub fn dilate(img: &Image<L8>, se: &StructuringElement) -> Image<L8> {
+    rank(img, se, u8::max, u8::MIN)
+}
+
+// removes foreground smaller than the element
+pub fn open(img: &Image<L8>, se: &StructuringElement) -> Image<L8> {
+    dilate(&erode(img, se), se)
+}
+
+// removes gaps and holes smaller than the element
+pub fn close(img: &Image<L8>, se: &StructuringElement) -> Image<L8> {
+    erode(&dilate(img, se), se)
+}
+
+// Binary
+// sets all background that isn't 4-connected to the image border
+pub fn fill_holes(img: &Image<L8>) -> Image<L8> {
+    let (width, height) = (img.width(), img.height());
+
+    let mut out = Image::from_raw(width, height, vec![u8::MAX; width * height])
+        .expect("fill_holes: unexpected image size");
+
+    let data = img.data();
+    let filled = out.data_mut();
+
+    let mut stack: Vec<(usize, usize)> = vec![];
+
+    for y in 0..height {
+        for x in 0..width {
+            if x == 0 || y == 0 || x + 1 == width || y + 1 == height {
+                stack.push((x, y));
+            }
+        }
+    }
+
+    // flood the outside background
+    while let Some((x, y)) = stack.pop() {
+        let idx = y * width + x;
+
+        if data[idx] != 0 || filled[idx] == 0 { continue }
+
+        filled[idx] = 0;
+
+        if x > 0 { stack.push((x - 1, y)) }
+        if y > 0 { stack.push((x, y - 1)) }
+        if x + 1 < width { stack.push((x + 1, y)) }
+        if y + 1 < height { stack.push((x, y + 1)) }
+    }
+    out
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // '#' is foreground
+    fn mask(rows: &[&str]) -> Image<L8> {
+        let data = rows.iter()
+            .flat_map(|row| row.chars().map(|c| if c == '#' { u8::MAX } else { u8::MIN }))
+            .collect();
+
+        Image::from_raw(rows[0].len(), rows.len(), data).unwrap()
+    }
+
+    fn rows(img: &Image<L8>) -> Vec<String> {
+        img.data().chunks_exact(img.width())
+            .map(|row| row.iter().map(|&v| if v != 0 { '#' } else { '.' }).collect())
+            .collect()
+    }
+
+    #[test]
+    fn erode_shrinks_by_the_element() {
+        let img = mask(&[
+            ".....",
+            ".###.",
+            ".###.",
+            ".###.",
+            ".....",
+        ]);
+
+        assert_eq!(rows(&erode(&img, &StructuringElement::Square(1))), [
+            ".....",
+            ".....",
+            "..#..",
+            ".....",
+            ".....",
+        ]);
+    }
+
+    #[test]
+    fn erode_ignores_pixels_outside_the_image() {
+        let img = mask(&[
+            "###..",
+            "###..",
+            "###..",
+            ".....",
+        ]);
+
+        assert_eq!(rows(&erode(&img, &StructuringElement::Square(1))), [
+            "##...",
+            "##...",
+            ".....",
+            ".....",
+        ]);
+    }
+
+    #[test]
+    fn dilate_grows_by_the_element() {
+        let img = mask(&[
+            "#....",
+            ".....",
+            "..#..",
+            ".....",
+        ]);
+
+        assert_eq!(rows(&dilate(&img, &StructuringElement::Cross(1))), [
+            "##...",
+            "#.#..",
+            ".###.",
+            "..#..",
+        ]);
+        assert_eq!(rows(&dilate(&img, &StructuringElement::Square(1))), [
+            "##...",
+            "####.",
+            ".###.",
+            ".###.",
+        ]);
+    }
+
+    #[test]
+    fn disks_leave_out_the_corners() {
+        let img = mask(&[
+            ".....",
+            ".....",
+            "..#..",
+            ".....",
+            ".....",
+        ]);
+
+        assert_eq!(rows(&dilate(&img, &StructuringElement::Disk(2))), [
+            "..#..",
+            ".###.",
+            "#####",
+            ".###.",
+            "..#..",
+        ]);
+    }
+
+    #[test]
+    fn open_removes_specks_and_keeps_blobs() {
+        let img = mask(&[
+            "#......",
+            ".......",
+            "...###.",
+            "...###.",
+            "...###.",
+        ]);
+
+        assert_eq!(rows(&open(&img, &StructuringElement::Square(1))), [
+            ".......",
+            ".......",
+            "...###.",
+            "...###.",
+            "...###.",
+        ]);
+    }
+
+    #[test]
+    fn close_bridges_gaps() {
+        let img = mask(&[
+            ".........",
+            ".........",
+            "..##.##..",
+            "..##.##..",
+            ".........",
+            ".........",
+        ]);
+
+        assert_eq!(rows(&close(&img, &StructuringElement::Square(1))), [
+            ".........",
+            ".........",
+            "..#####..",
+            "..#####..",
+            ".........",
+            ".........",
+        ]);
+    }
+
+    #[test]
+    fn fill_holes_keeps_background_open_to_the_border() {
+        let img = mask(&[
+            "#.#....",
+            "###....",
+            "...###.",
+            "...#.#.",
+            "...###.",
+        ]);
+
+        assert_eq!(rows(&fill_holes(&img)), [
+            "#.#....",
+            "###....",
+            "...###.",
+            "...###.",
+            "...###.",
+        ]);
+    }
+
+    #[test]
+    fn cleanup_applies_the_steps_in_order() {
+        let img = mask(&[
+            "#........",
+            ".........",
+            "..#####..",
+            "..#...#..",
+            "..#####..",
+            ".........",
+        ]);
+
+        let se = StructuringElement::Square(1);
+
+        assert_eq!(cleanup(img.clone(), &[Cleanup::FillHoles, Cleanup::Open(se)]), open(&fill_holes(&img), &se));
+        assert_eq!(rows(&cleanup(img, &[Cleanup::FillHoles, Cleanup::Open(se)])), [
+            ".........",
+            ".........",
+            "..#####..",
+            "..#####..",
+            "..#####..",
+            ".........",
+        ]);
+    }
+}
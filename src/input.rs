@@ -0,0 +1,107 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+
+// Destination of the robot's mouse commands
+pub trait InputSink {
+    // t is the capture time of the frame the command responds to
+    fn send(&mut self, t: Duration, command: &MouseCommand);
+}
+
+// Keeps every command instead of sending it
+// nothing is dropped, long runs should drain it regularly
+#[derive(Default)]
+pub struct RecordingInput {
+    commands: Vec<(Duration, MouseCommand)>,
+}
+
+impl RecordingInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // with the capture time of the frame each responds to, oldest first
+    pub fn commands(&self) -> &[(Duration, MouseCommand)] {
+        &self.commands
+    }
+
+    // takes the commands sent so far
+    pub fn drain(&mut self) -> Vec<(Duration, MouseCommand)> {
+        std::mem::take(&mut self.commands)
+    }
+}
+
+impl InputSink for RecordingInput {
+    fn send(&mut self, t: Duration, command: &MouseCommand) {
+        self.commands.push((t, command.clone()));
+    }
+}
+
+// mouse commands in screen pixels
+#[derive(Debug, Clone, PartialEq)]
+pub enum MouseCommand {
+    Move { x: usize, y: usize },
+    Press,
+    Release,
+}
+
+// "move <x> <y>", "press" or "release"
+impl fmt::Display for MouseCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MouseCommand::Move { x, y } => write!(f, "move {} {}", x, y),
+            MouseCommand::Press => write!(f, "press"),
+            MouseCommand::Release => write!(f, "release"),
+        }
+    }
+}
+
+impl FromStr for MouseCommand {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_whitespace().collect::<Vec<_>>().as_slice() {
+            ["move", x, y] => Ok(MouseCommand::Move {
+                x: x.parse().map_err(|_| ())?,
+                y: y.parse().map_err(|_| ())?,
+            }),
+            ["press"] => Ok(MouseCommand::Press),
+            ["release"] => Ok(MouseCommand::Release),
+            _ => Err(()),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_round_trip() {
+        for command in [MouseCommand::Move { x: 12, y: 3400 }, MouseCommand::Press, MouseCommand::Release] {
+            assert_eq!(command.to_string().parse(), Ok(command));
+        }
+    }
+
+    #[test]
+    fn malformed_commands_are_rejected() {
+        for s in ["", "move", "move 1", "move 1 2 3", "move -1 2", "click", "press now"] {
+            assert_eq!(s.parse::<MouseCommand>(), Err(()), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn recording_input_drains() {
+        let mut input = RecordingInput::new();
+
+        input.send(Duration::from_millis(1), &MouseCommand::Press);
+        input.send(Duration::from_millis(2), &MouseCommand::Release);
+
+        assert_eq!(input.commands().len(), 2);
+        assert_eq!(input.drain(), vec![
+            (Duration::from_millis(1), MouseCommand::Press),
+            (Duration::from_millis(2), MouseCommand::Release),
+        ]);
+        assert!(input.commands().is_empty());
+    }
+}
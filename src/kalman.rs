@@ -2,8 +2,6 @@
 #![allow(clippy::nonstandard_macro_braces)]
 
 
-use std::time::Instant;
-
 use nalgebra::{DMatrix, DVector, dmatrix, dvector};
 
 
@@ -35,12 +33,10 @@ pub struct KalmanFilter {
 
     // measurement noise (covariance matrix)
     r: DMatrix<f32>,
-
-    t_last: Instant,
 }
 
-impl KalmanFilter {
-    pub fn default() -> KalmanFilter {
+impl Default for KalmanFilter {
+    fn default() -> KalmanFilter {
         let x = dvector![0.0, 0.0, 0.0, 0.0];
 
         let p = {
@@ -56,40 +52,52 @@ impl KalmanFilter {
                 0.0, 0.0, 0.0,  VV]
         };
 
-        let q = {
-            // higher => trust data more, prediction less
-            const Q: f32 = 6.0;
-            dmatrix![
-                0.0,   Q, 0.0, 0.0;
-                  Q,   Q, 0.0, 0.0;
-                0.0, 0.0, 0.0,   Q;
-                0.0, 0.0,   Q,   Q]
-        };
+        let q = process_noise(DEFAULT_PROCESS_NOISE);
 
         let h = dmatrix![
             1.0, 0.0, 0.0, 0.0;
             0.0, 0.0, 1.0, 0.0];
 
-        let r = {
-            // higher => trust prediction more, data less
-            const R: f32 = 14.0;
-            dmatrix![
-                R, 0.0;
-                0.0, R]
-        };
+        let r = measurement_noise(DEFAULT_MEASUREMENT_NOISE);
 
-        let t_last = Instant::now();
+        KalmanFilter { x, p, q, h, r }
+    }
+}
+
+// higher => trust data more, prediction less
+pub const DEFAULT_PROCESS_NOISE: f32 = 6.0;
+// higher => trust prediction more, data less
+pub const DEFAULT_MEASUREMENT_NOISE: f32 = 14.0;
+
+fn process_noise(q: f32) -> DMatrix<f32> {
+    dmatrix![
+        0.0,   q, 0.0, 0.0;
+          q,   q, 0.0, 0.0;
+        0.0, 0.0, 0.0,   q;
+        0.0, 0.0,   q,   q]
+}
+
+fn measurement_noise(r: f32) -> DMatrix<f32> {
+    dmatrix![
+          r, 0.0;
+        0.0,   r]
+}
 
-        KalmanFilter { x, p, q, h, r, t_last }
+impl KalmanFilter {
+    // at rest on a first measurement
+    pub fn at(position: (f32, f32), process: f32, measurement: f32) -> Self {
+        KalmanFilter {
+            x: dvector![position.0, 0.0, position.1, 0.0],
+            q: process_noise(process),
+            r: measurement_noise(measurement),
+            ..KalmanFilter::default()
+        }
     }
 
     // x = F * x + B * u
     // P = F * P * F^T + Q
-    pub fn predict(&mut self) {
-        let t = Instant::now();
-        let dt = t.duration_since(self.t_last).as_secs_f32();
-        self.t_last = t;
-
+    // dt in seconds since the last prediction
+    pub fn predict(&mut self, dt: f32) {
         // transition function
         let f = dmatrix![
             1.0,  dt, 0.0, 0.0;
@@ -120,7 +128,28 @@ impl KalmanFilter {
         self.p = &self.p - &k * &self.h * &self.p;
     }
 
+    // squared Mahalanobis distance of a measurement from the predicted one
+    // y^T * (H*P*H^T + R)^-1 * y
+    pub fn distance(&self, z: &(f32, f32)) -> f32 {
+        let z = dvector![z.0, z.1];
+        let y = z - &self.h * &self.x;
+
+        let s = &self.h * &self.p * self.h.transpose() + &self.r;
+
+        (y.transpose() * s.try_inverse().unwrap() * &y)[0]
+    }
+
+    // keeps the state, takes effect from the next prediction
+    pub fn set_noise(&mut self, process: f32, measurement: f32) {
+        self.q = process_noise(process);
+        self.r = measurement_noise(measurement);
+    }
+
     pub fn position(&self) -> (f32, f32) {
         (self.x[0], self.x[2])
     }
+
+    pub fn velocity(&self) -> (f32, f32) {
+        (self.x[1], self.x[3])
+    }
 }
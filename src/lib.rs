@@ -0,0 +1,31 @@
+#![allow(clippy::option_map_unit_fn)]
+
+pub mod robot;
+pub mod img;
+pub mod img_color;
+pub mod img_connected_components;
+pub mod img_morphology;
+#[cfg(feature = "overlay")]
+pub mod overlay;
+pub mod capture;
+#[cfg(all(windows, feature = "windows-capture"))]
+pub mod capture_windows;
+pub mod capture_directory;
+#[cfg(feature = "overlay")]
+pub mod rectangle_renderer;
+#[cfg(feature = "overlay")]
+pub mod rectangle_data;
+pub mod background;
+pub mod background_adaptive;
+pub mod kalman;
+pub mod session;
+pub mod input;
+pub mod replay;
+pub mod clock;
+pub mod config;
+pub mod coordinates;
+pub mod detector;
+pub mod frame_result;
+pub mod pipeline;
+pub mod prediction;
+pub mod tracker;
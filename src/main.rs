@@ -1,43 +1,50 @@
-#![allow(clippy::option_map_unit_fn)]
+use bumper_robot::{capture_directory::DirectoryCapture, config::{Config, ConfigWatcher, DETECTOR_CONFIG}, input::RecordingInput, pipeline::Pipeline, replay::replay, robot::Robot, session::SessionReader};
 
-use std::time::{Duration, Instant};
+#[cfg(all(windows, feature = "windows-capture"))]
+use {
+    std::time::Duration,
+    bumper_robot::{capture_windows::{Capture, WindowsInput}, clock::{Clock, SystemClock}, session::SessionWriter},
+};
 
-use capture_windows::mouse_press;
-use img::Rectangle;
-use robot::Robot;
-use winit::{event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent}, event_loop::{ControlFlow, EventLoop}};
-
-use crate::{capture::{Capture}, overlay::Overlay};
-
-
-pub mod robot;
-pub mod img;
-pub mod img_connected_components;
-pub mod overlay;
-pub mod capture;
-pub mod capture_windows;
-pub mod rectangle_renderer;
-pub mod rectangle_data;
-pub mod background;
-pub mod kalman;
+#[cfg(all(windows, feature = "windows-capture", feature = "overlay"))]
+use {
+    winit::{event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent}, event_loop::{ControlFlow, EventLoop}},
+    bumper_robot::{capture, overlay::Overlay},
+};
 
 
 enum Execution {
-    Headless(Duration),
+    #[cfg(all(windows, feature = "windows-capture"))]
+    Headless(Capture, Duration),
+    Directory(DirectoryCapture),
+    Replay(SessionReader),
+    #[cfg(all(windows, feature = "windows-capture", feature = "overlay"))]
     UseOverlay {
+        capture: Capture,
         event_loop: Box<EventLoop<()>>,
         overlay: Box<Overlay>,
     },
 }
 
 fn main() {
-    let mut capture = Capture::new("Ys Chronicles+: Ancient Ys Vanished - Omen")
-        .expect("Couldn't capture window");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let argument = |short: &str, long: &str| args.iter()
+        .position(|a| a == short || a == long)
+        .map(|i| args.get(i + 1).unwrap_or_else(|| panic!("Missing value for {}", long)).as_str());
+
+    #[cfg(all(windows, feature = "windows-capture"))]
+    let window_title = argument("-w", "--window").unwrap_or("Ys Chronicles+: Ancient Ys Vanished - Omen");
+
+    // capture times are relative to startup
+    #[cfg(all(windows, feature = "windows-capture"))]
+    let clock = SystemClock::new();
 
-    let mut robot = Robot::new().expect("Unable to initialize robot");
+    let execution_mode = match args.first().map(String::as_str) {
+        #[cfg(all(windows, feature = "windows-capture", feature = "overlay"))]
+        Some("-o" | "--overlay") => {
+            let capture = Capture::new(window_title, clock).expect("Couldn't capture window");
 
-    let execution_mode =
-        if let Some("-o" | "--overlay") = std::env::args().nth(1).as_deref() {
             let event_loop = Box::new(EventLoop::new());
 
             let overlay = Box::new(futures::executor::block_on(Overlay::new(
@@ -45,23 +52,118 @@ fn main() {
                 &capture.window
             )));
 
-            Execution::UseOverlay { event_loop, overlay }
-        } else {
-            Execution::Headless(Duration::from_secs(10))
-        };
+            Execution::UseOverlay { capture, event_loop, overlay }
+        },
+        Some("-d" | "--directory") => {
+            let directory = argument("-d", "--directory").unwrap();
+
+            Execution::Directory(DirectoryCapture::new(directory).expect("Couldn't open frame directory"))
+        },
+        Some("--replay") => {
+            let directory = argument("--replay", "--replay").unwrap();
+
+            Execution::Replay(SessionReader::open(directory)
+                .unwrap_or_else(|e| panic!("Couldn't open session {}: {}", directory, e)))
+        },
+        #[cfg(all(windows, feature = "windows-capture"))]
+        _ => Execution::Headless(
+            Capture::new(window_title, clock).expect("Couldn't capture window"),
+            Duration::from_secs(10)),
+        #[cfg(not(all(windows, feature = "windows-capture")))]
+        _ => panic!("Capturing the game window requires Windows and the windows-capture feature, use --directory or --replay"),
+    };
+
+    if argument("-r", "--record").is_some() && matches!(execution_mode, Execution::Directory(_) | Execution::Replay(_)) {
+        panic!("Recording is only supported when capturing the game window");
+    }
+
+    // where a learned background is saved, nothing is saved without it
+    let save_background = argument("--save-background", "--save-background");
+
+    if save_background.is_some() && matches!(execution_mode, Execution::Replay(_)) {
+        panic!("Saving the background isn't supported when replaying");
+    }
+
+    // the default file is optional
+    let config_file = match argument("-c", "--config") {
+        Some(filename) => Some(filename),
+        None => Some(DETECTOR_CONFIG).filter(|f| std::path::Path::new(f).exists()),
+    };
+
+    let config = config_file
+        .map_or_else(|| Ok(Config::default()), Config::load)
+        .unwrap_or_else(|e| panic!("Invalid detector configuration: {}", e));
+
+    // prints every frame's detections, action and timings
+    let verbose = args.iter().any(|a| a == "-v" || a == "--verbose");
+
+    // picks up edits to the config file while running
+    let watcher = || config_file.map(ConfigWatcher::new);
+
+    // frames are only recorded from the game window
+    #[cfg(all(windows, feature = "windows-capture"))]
+    let recorder = |window, robot: &Robot<WindowsInput>| argument("-r", "--record")
+        .map(|directory| SessionWriter::create(directory, window, &robot.session_background()).expect("Unable to create session"));
+
+    const ROBOT_ERROR: &str = "Unable to initialize robot";
 
     match execution_mode {
-        Execution::Headless(time_limit) => {
-            let t0 = Instant::now();
+        #[cfg(all(windows, feature = "windows-capture"))]
+        Execution::Headless(capture, time_limit) => {
+            let robot = Robot::new(WindowsInput, config, save_background).expect(ROBOT_ERROR);
+            let recorder = recorder(&capture.window, &robot);
 
-            while (Instant::now() - t0) < time_limit {
-                tick(&mut capture, &mut robot);
+            let mut pipeline = Pipeline::new(capture, robot);
 
-                // FIXME: workaround for the overlay taking focus on input
-                mouse_press();
+            if let Some(recorder) = recorder {
+                pipeline.record(recorder);
+            }
+            if let Some(watcher) = watcher() {
+                pipeline.watch(watcher);
+            }
+
+            // the time limit doesn't include loading or estimating the background
+            let t0 = clock.now();
+
+            while clock.now() - t0 < time_limit {
+                if let Some(result) = pipeline.tick() {
+                    if verbose { eprintln!("{}", result) }
+                }
+            }
+        },
+        Execution::Directory(source) => {
+            let mut pipeline = Pipeline::new(source, Robot::new(RecordingInput::new(), config, save_background).expect(ROBOT_ERROR));
+
+            if let Some(watcher) = watcher() {
+                pipeline.watch(watcher);
+            }
+
+            while let Some(result) = pipeline.tick() {
+                if verbose { eprintln!("{}", result) }
+
+                // nothing reads the commands after the frame
+                pipeline.robot_mut().input_mut().drain();
+            }
+        },
+        Execution::Replay(session) => {
+            if replay(&session, config).expect(ROBOT_ERROR) > 0 {
+                std::process::exit(1);
             }
         },
-        Execution::UseOverlay{ event_loop, mut overlay } => {
+        #[cfg(all(windows, feature = "windows-capture", feature = "overlay"))]
+        Execution::UseOverlay{ capture, event_loop, mut overlay } => {
+            let robot = Robot::new(WindowsInput, config, save_background).expect(ROBOT_ERROR);
+            let recorder = recorder(&capture.window, &robot);
+
+            let mut pipeline = Pipeline::new(capture, robot);
+
+            if let Some(recorder) = recorder {
+                pipeline.record(recorder);
+            }
+            if let Some(watcher) = watcher() {
+                pipeline.watch(watcher);
+            }
+
             event_loop.run(move |event, _, control_flow| match event {
                 Event::WindowEvent { event, window_id } if window_id == overlay.window.id() =>
                 match event {
@@ -91,12 +193,14 @@ fn main() {
                     }
                 },
                 Event::MainEventsCleared => {
-                    if let Some(rectangles) = tick(&mut capture, &mut robot) {
+                    if let Some(result) = pipeline.tick() {
+                        if verbose { eprintln!("{}", result) }
 
                         overlay.clear();
-                        for r in &rectangles {
-                            let r = capture::active_pixel_to_window_normalized(r, &capture.window);
-                            overlay.add(&r);
+                        for d in &result.detections {
+                            if let Some(r) = capture::active_pixel_to_window_normalized(&d.bbox, &pipeline.source().window) {
+                                overlay.add(&r, &d.class);
+                            }
                         }
                         overlay.window.request_redraw()
                     }
@@ -106,13 +210,3 @@ fn main() {
         }
     }
 }
-
-fn tick(capture: &mut Capture, robot: &mut Robot) -> Option<Vec<Rectangle<usize>>> {
-    if let Some((frame, active_area)) = capture.try_frame() {
-
-        let rectangles = robot.process_frame(frame, &active_area);
-
-        return Some(rectangles)
-    }
-    None
-}
@@ -1,6 +1,6 @@
 use winit::{dpi::{PhysicalPosition, PhysicalSize, Position, Size}, event_loop::EventLoop, window::{Window, WindowBuilder}};
 
-use crate::{capture::ACTIVE_RECTANGLE, img::Rectangle, rectangle_renderer::RectangleRenderer};
+use crate::{capture::ACTIVE_RECTANGLE, coordinates::{Rectangle, Screen, WindowNormalized}, detector::DetectionClass, rectangle_renderer::RectangleRenderer};
 
 
 pub struct Overlay {
@@ -19,7 +19,7 @@ pub struct Overlay {
 
 impl Overlay {
     // only call once (TODO: enforce this?)
-    pub async fn new(event_loop: &EventLoop<()>, window_rectangle: &Rectangle<usize>) -> Self {
+    pub async fn new(event_loop: &EventLoop<()>, window_rectangle: &Rectangle<usize, Screen>) -> Self {
         const TITLE: &str = "Bumper Robot Overlay";
 
         let window = WindowBuilder::new()
@@ -68,9 +68,7 @@ impl Overlay {
 
         let mut rr = RectangleRenderer::new(&device, swap_chain_descriptor.format);
 
-        rr.add_permanent(&Rectangle {
-            left: 0.0, top: 0.0, width: 1.0, height: 1.0 },
-            &[0.5, 0.25, 0.125]);
+        rr.add_permanent(&Rectangle::new(0.0, 0.0, 1.0, 1.0), &[0.5, 0.25, 0.125]);
 
         rr.add_permanent(&ACTIVE_RECTANGLE, &[0.8, 0.3, 0.3]);
 
@@ -91,8 +89,16 @@ impl Overlay {
         self.queue.write_buffer(&self.rr.instance_buffer, 0, bytemuck::cast_slice(&rectangle_instances));
     }
 
-    pub fn add(&mut self, rectangle: &Rectangle<f32>) {
-        self.rr.add(rectangle, &[0.0, 1.0, 0.0]);
+    // colored by class
+    pub fn add(&mut self, rectangle: &Rectangle<f32, WindowNormalized>, class: &DetectionClass) {
+        let color = match class {
+            DetectionClass::TargetHead => [0.0, 1.0, 0.0],
+            DetectionClass::OtherHead => [0.0, 0.0, 1.0],
+            DetectionClass::Player => [1.0, 1.0, 0.0],
+            DetectionClass::Projectile(_) => [1.0, 0.0, 0.0],
+        };
+
+        self.rr.add(rectangle, &color);
     }
 
     pub fn clear(&mut self) {
@@ -0,0 +1,245 @@
+use crate::{capture::FrameSource, config::ConfigWatcher, frame_result::FrameResult, input::InputSink, robot::Robot, session::{SessionFrame, SessionWriter}};
+
+
+// Fetches frames from a source and hands them to the robot, which responds through its input sink
+pub struct Pipeline<S: FrameSource, I: InputSink> {
+    source: S,
+    robot: Robot<I>,
+    recorder: Option<SessionWriter>,
+    watcher: Option<ConfigWatcher>,
+}
+
+impl<S: FrameSource, I: InputSink> Pipeline<S, I> {
+    pub fn new(source: S, robot: Robot<I>) -> Self {
+        Pipeline { source, robot, recorder: None, watcher: None }
+    }
+
+    // records every processed frame from now on
+    pub fn record(&mut self, recorder: SessionWriter) {
+        self.recorder = Some(recorder);
+    }
+
+    // reloads the robot's config between frames whenever the file changes
+    pub fn watch(&mut self, watcher: ConfigWatcher) {
+        self.watcher = Some(watcher);
+    }
+
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    pub fn robot(&self) -> &Robot<I> {
+        &self.robot
+    }
+
+    pub fn robot_mut(&mut self) -> &mut Robot<I> {
+        &mut self.robot
+    }
+
+    // None when no frame is available
+    pub fn tick(&mut self) -> Option<FrameResult> {
+        self.reload();
+
+        let frame = self.source.try_frame()?;
+
+        let t = frame.t;
+        let active_area = frame.active_area.clone();
+
+        // process_frame consumes the frame
+        let recorded_frame = self.recorder.as_ref().map(|_| frame.img.clone());
+
+        let result = self.robot.process_frame(frame);
+
+        if let (Some(recorder), Some(img)) = (&mut self.recorder, recorded_frame) {
+            let frame = SessionFrame {
+                t,
+                active_area,
+                detections: result.detections.clone(),
+                commands: self.robot.commands().to_vec(),
+            };
+
+            if let Err(e) = recorder.write_frame(&img, &frame) {
+                eprintln!("Unable to record frame: {}", e);
+            }
+        }
+
+        Some(result)
+    }
+
+    fn reload(&mut self) {
+        let watcher = match &mut self.watcher {
+            Some(watcher) => watcher,
+            None => return,
+        };
+
+        let result = match watcher.poll() {
+            Some(result) => {
+                let robot = &mut self.robot;
+                result.and_then(|config| robot.set_config(config))
+            },
+            None => return,
+        };
+
+        match result {
+            Ok(changes) if changes.is_empty() => eprintln!("Reloaded {}: no changes", watcher.filename()),
+            Ok(changes) => {
+                eprintln!("Reloaded {}:", watcher.filename());
+                for change in changes {
+                    eprintln!("  {}", change);
+                }
+            },
+            Err(e) => eprintln!("Keeping the previous config, {}", e),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{background::Background, background_adaptive::AdaptiveConfig, capture_directory::DirectoryCapture, config::Config, detector::DetectionClass, img::{IMAGE_DOWNSCALE_FACTOR, Image, Rgb8}, input::{MouseCommand, RecordingInput}, session::{SessionBackground, SessionReader}};
+
+    const WIDTH: usize = 320;
+    const HEIGHT: usize = 240;
+
+    // one black frame of the active area at (100, 50) with a blue head in its top left
+    fn fixture(name: &str) -> std::path::PathBuf {
+        let directory = std::env::temp_dir().join(format!("bumper_robot_pipeline_{}_{}", name, std::process::id()));
+
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("active_area.txt"), format!("100 50 {} {}", WIDTH, HEIGHT)).unwrap();
+
+        let mut data = vec![0u8; WIDTH * HEIGHT * 3];
+
+        for y in 80..120 {
+            for x in 40..80 {
+                data[3 * (y * WIDTH + x) + 2] = 150;
+            }
+        }
+        image::save_buffer(directory.join("frame0.png"), &data, WIDTH as u32, HEIGHT as u32, image::ColorType::Rgb8).unwrap();
+
+        directory
+    }
+
+    fn robot() -> Robot<RecordingInput> {
+        let background: Image<Rgb8> = Image::new(WIDTH / IMAGE_DOWNSCALE_FACTOR, HEIGHT / IMAGE_DOWNSCALE_FACTOR);
+
+        Robot::with_background(RecordingInput::new(), Config::default(), Background::from_image(background)).unwrap()
+    }
+
+    #[test]
+    fn moves_to_the_mirrored_head() {
+        let directory = fixture("move");
+
+        let source = DirectoryCapture::new(directory.to_str().unwrap()).unwrap();
+        let mut pipeline = Pipeline::new(source, robot());
+
+        let result = pipeline.tick().unwrap();
+
+        let other = result.of_class(&DetectionClass::OtherHead).next().unwrap();
+        let target = result.of_class(&DetectionClass::TargetHead).next().unwrap();
+
+        assert_eq!(other.confidence, 1.0);
+        // the blue head is on the left, the target on the right
+        assert!(other.centroid.x < target.centroid.x);
+
+        let sent = pipeline.robot().input().commands();
+
+        assert_eq!(sent.len(), 2);
+        assert_eq!(Some(&sent[0].1), result.action.as_ref());
+        assert_eq!(sent[0].0, Duration::ZERO);
+        assert_eq!(sent[1], (Duration::ZERO, MouseCommand::Press));
+
+        match sent[0].1 {
+            MouseCommand::Move { x, y } => {
+                assert!((100 + WIDTH / 2..100 + WIDTH).contains(&x), "x {}", x);
+                assert!((50..50 + HEIGHT).contains(&y), "y {}", y);
+            },
+            ref command => panic!("unexpected {}", command),
+        }
+
+        assert!(pipeline.tick().is_none());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn mismatched_background_is_learned_instead() {
+        let directory = fixture("mismatch");
+
+        let source = DirectoryCapture::new(directory.to_str().unwrap()).unwrap();
+        let background = Background::from_image(Image::new(10, 10));
+
+        let mut pipeline = Pipeline::new(source, Robot::with_background(RecordingInput::new(), Config::default(), background).unwrap());
+
+        let result = pipeline.tick().unwrap();
+
+        // nothing is seen, the heads only coast
+        assert!(result.detections.iter().all(|d| d.confidence == 0.0));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn records_what_it_processed() {
+        let directory = fixture("record");
+        let session = directory.join("session");
+
+        let source = DirectoryCapture::new(directory.to_str().unwrap()).unwrap();
+        let window = source.active_area.clone();
+
+        let robot = robot();
+        let recorder = SessionWriter::create(session.to_str().unwrap(), &window, &robot.session_background()).unwrap();
+
+        let mut pipeline = Pipeline::new(source, robot);
+        pipeline.record(recorder);
+
+        let result = pipeline.tick().unwrap();
+        drop(pipeline);
+
+        let reader = SessionReader::open(session.to_str().unwrap()).unwrap();
+        let recorded = reader.frame(0).unwrap();
+
+        assert_eq!(reader.len(), 1);
+        // the press follows the move
+        assert_eq!(recorded.commands, vec![result.action.unwrap(), MouseCommand::Press]);
+        assert_eq!(recorded.detections.len(), result.detections.len());
+        assert_eq!(reader.image(0).unwrap().width(), WIDTH);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn replays_match_the_recording() {
+        let directory = fixture("replay");
+        std::fs::copy(directory.join("frame0.png"), directory.join("frame1.png")).unwrap();
+
+        let learned = AdaptiveConfig { warmup_frames: 1, ..AdaptiveConfig::default() };
+
+        for (name, robot) in [
+            ("fixed", robot()),
+            ("learned", Robot::with_session_background(RecordingInput::new(), Config::default(), SessionBackground::Learned(learned)).unwrap()),
+        ] {
+            let session = directory.join(name);
+
+            let source = DirectoryCapture::new(directory.to_str().unwrap()).unwrap();
+            let recorder = SessionWriter::create(session.to_str().unwrap(), &source.active_area.clone(), &robot.session_background()).unwrap();
+
+            let mut pipeline = Pipeline::new(source, robot);
+            pipeline.record(recorder);
+
+            while pipeline.tick().is_some() {}
+            drop(pipeline);
+
+            let reader = SessionReader::open(session.to_str().unwrap()).unwrap();
+
+            assert_eq!(reader.len(), 2);
+            assert_eq!(crate::replay::replay(&reader, Config::default()).unwrap(), 0, "{}", name);
+        }
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
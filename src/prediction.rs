@@ -0,0 +1,280 @@
+use std::fmt;
+
+use crate::{config::PredictionConfig, coordinates::{Downscaled, Point, Rectangle}, detector::{Detection, DetectionClass}};
+
+
+// Where a tracked projectile is headed relative to the player
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prediction {
+    pub track_id: u32,
+    pub class: DetectionClass,
+    // centroid every config.step seconds from now on, starting with the current one
+    pub path: Vec<Point<f32, Downscaled>>,
+    // smallest distance between the path and the player, and seconds until then
+    pub closest_distance: f32,
+    pub closest_time: f32,
+    // seconds until the path first comes within config.impact_radius of the player
+    pub time_to_impact: Option<f32>,
+}
+
+impl Prediction {
+    // None for untracked detections
+    // the projectile bounces off the bounds with its bounding box, the player is assumed to stand still
+    pub fn new(detection: &Detection, bounds: &Rectangle<usize, Downscaled>, player: &Point<f32, Downscaled>, config: &PredictionConfig) -> Option<Self> {
+        let track_id = detection.track_id?;
+        let velocity = detection.velocity?;
+
+        let path = trajectory(detection, velocity, bounds, config);
+
+        let mut prediction = Prediction {
+            track_id,
+            class: detection.class.clone(),
+            closest_distance: f32::INFINITY,
+            closest_time: 0.0,
+            time_to_impact: None,
+            path,
+        };
+
+        let distance = |a: &Point<f32, Downscaled>, b: &Point<f32, Downscaled>| (a.x - b.x).hypot(a.y - b.y);
+
+        if prediction.path.len() == 1 {
+            prediction.closest_distance = distance(&prediction.path[0], player);
+        }
+
+        // the path is straight between samples
+        for (i, segment) in prediction.path.windows(2).enumerate() {
+            let (a, b) = (&segment[0], &segment[1]);
+            let t0 = i as f32 * config.step;
+
+            let s = closest_on_segment(a, b, player);
+            let closest = interpolate(a, b, s);
+            let d = distance(&closest, player);
+
+            if d < prediction.closest_distance {
+                prediction.closest_distance = d;
+                prediction.closest_time = t0 + s * config.step;
+            }
+
+            if prediction.time_to_impact.is_none() {
+                prediction.time_to_impact = entry_on_segment(a, b, player, config.impact_radius)
+                    .map(|s| t0 + s * config.step);
+            }
+        }
+
+        Some(prediction)
+    }
+}
+
+// "track <id> <class>: closest <distance> in <time>, impact in <time> | no impact"
+impl fmt::Display for Prediction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "track {} {}: closest {:.1} in {:.2}s, ", self.track_id, self.class, self.closest_distance, self.closest_time)?;
+
+        match self.time_to_impact {
+            Some(t) => write!(f, "impact in {:.2}s", t),
+            None => write!(f, "no impact"),
+        }
+    }
+}
+
+// constant velocity steps, reflected at the bounds shrunk by half the bounding box
+fn trajectory(detection: &Detection, velocity: (f32, f32), bounds: &Rectangle<usize, Downscaled>, config: &PredictionConfig) -> Vec<Point<f32, Downscaled>> {
+    let bbox = &detection.bbox;
+
+    let half_width = bbox.width as f32 / 2.0;
+    let half_height = bbox.height as f32 / 2.0;
+
+    let x_bounds = (bounds.left as f32 + half_width, (bounds.left + bounds.width) as f32 - half_width);
+    let y_bounds = (bounds.top as f32 + half_height, (bounds.top + bounds.height) as f32 - half_height);
+
+    let (mut x, mut y) = (detection.centroid.x, detection.centroid.y);
+    let (mut vx, mut vy) = velocity;
+
+    let steps = (config.horizon / config.step).round() as usize;
+
+    let mut path = Vec::with_capacity(steps + 1);
+    path.push(Point::new(x, y));
+
+    for _ in 0..steps {
+        x += vx * config.step;
+        y += vy * config.step;
+
+        bounce(&mut x, &mut vx, x_bounds);
+        bounce(&mut y, &mut vy, y_bounds);
+
+        path.push(Point::new(x, y));
+    }
+    path
+}
+
+// mirrors a position that left [lower, upper] back inside and reverses its velocity
+// bounds too narrow for the object don't bounce
+fn bounce(p: &mut f32, v: &mut f32, (lower, upper): (f32, f32)) {
+    if lower >= upper { return }
+
+    if *p < lower {
+        *p = (2.0 * lower - *p).min(upper);
+        *v = -*v;
+    } else if *p > upper {
+        *p = (2.0 * upper - *p).max(lower);
+        *v = -*v;
+    }
+}
+
+fn interpolate(a: &Point<f32, Downscaled>, b: &Point<f32, Downscaled>, s: f32) -> Point<f32, Downscaled> {
+    Point::new(a.x + s * (b.x - a.x), a.y + s * (b.y - a.y))
+}
+
+// fraction of the way from a to b closest to p
+fn closest_on_segment(a: &Point<f32, Downscaled>, b: &Point<f32, Downscaled>, p: &Point<f32, Downscaled>) -> f32 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length_squared = dx * dx + dy * dy;
+
+    if length_squared == 0.0 { return 0.0 }
+
+    (((p.x - a.x) * dx + (p.y - a.y) * dy) / length_squared).clamp(0.0, 1.0)
+}
+
+// first fraction of the way from a to b within radius of p
+// solves |a + s (b - a) - p|^2 = radius^2
+fn entry_on_segment(a: &Point<f32, Downscaled>, b: &Point<f32, Downscaled>, p: &Point<f32, Downscaled>, radius: f32) -> Option<f32> {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let (fx, fy) = (a.x - p.x, a.y - p.y);
+
+    let c = fx * fx + fy * fy - radius * radius;
+
+    if c <= 0.0 { return Some(0.0) }
+
+    let qa = dx * dx + dy * dy;
+    let qb = 2.0 * (fx * dx + fy * dy);
+
+    let discriminant = qb * qb - 4.0 * qa * c;
+
+    if qa == 0.0 || discriminant < 0.0 { return None }
+
+    let s = (-qb - discriminant.sqrt()) / (2.0 * qa);
+
+    (0.0..=1.0).contains(&s).then_some(s)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOUNDS: Rectangle<usize, Downscaled> = Rectangle::new(0, 0, 40, 30);
+
+    fn config() -> PredictionConfig {
+        PredictionConfig { horizon: 1.0, step: 1.0 / 60.0, impact_radius: 3.0 }
+    }
+
+    // a tracked 2x2 projectile
+    fn projectile(x: f32, y: f32, velocity: (f32, f32)) -> Detection {
+        Detection {
+            centroid: Point::new(x, y),
+            track_id: Some(1),
+            velocity: Some(velocity),
+            ..Detection::new(DetectionClass::Projectile("fireball".to_string()), Rectangle::new(x as usize - 1, y as usize - 1, 2, 2), 1.0)
+        }
+    }
+
+    fn far() -> Point<f32, Downscaled> {
+        Point::new(1000.0, 1000.0)
+    }
+
+    #[test]
+    fn bounces_mirror_position_and_velocity() {
+        let (mut p, mut v) = (-2.0, -5.0);
+        bounce(&mut p, &mut v, (0.0, 10.0));
+        assert_eq!((p, v), (2.0, 5.0));
+
+        let (mut p, mut v) = (12.0, 5.0);
+        bounce(&mut p, &mut v, (0.0, 10.0));
+        assert_eq!((p, v), (8.0, -5.0));
+
+        let (mut p, mut v) = (4.0, 5.0);
+        bounce(&mut p, &mut v, (0.0, 10.0));
+        assert_eq!((p, v), (4.0, 5.0));
+    }
+
+    #[test]
+    fn paths_reflect_off_each_bound() {
+        // one pixel per step, the centroid stays half the bounding box inside
+        for (velocity, start) in [
+            ((-60.0, 0.0), (5.0, 15.0)),
+            ((60.0, 0.0), (35.0, 15.0)),
+            ((0.0, -60.0), (20.0, 5.0)),
+            ((0.0, 60.0), (20.0, 25.0)),
+        ] {
+            let prediction = Prediction::new(&projectile(start.0, start.1, velocity), &BOUNDS, &far(), &config()).unwrap();
+            let path = &prediction.path;
+
+            assert_eq!(path.len(), 61);
+            assert!(path.iter().all(|p| (1.0..=39.0).contains(&p.x) && (1.0..=29.0).contains(&p.y)), "{:?}", velocity);
+
+            // reaches the bound after 4 steps and comes back
+            let moved = |i: usize| (path[i].x - path[i - 1].x, path[i].y - path[i - 1].y);
+
+            assert!(((path[4].x - start.0).abs() + (path[4].y - start.1).abs() - 4.0).abs() < 1e-3, "{:?}", velocity);
+            assert!((moved(6).0 + velocity.0 / 60.0).abs() < 1e-3 && (moved(6).1 + velocity.1 / 60.0).abs() < 1e-3, "{:?}", velocity);
+        }
+    }
+
+    #[test]
+    fn bounds_narrower_than_the_box_dont_bounce() {
+        let narrow = Rectangle::new(0, 0, 1, 30);
+
+        let prediction = Prediction::new(&projectile(2.0, 15.0, (60.0, 0.0)), &narrow, &far(), &config()).unwrap();
+
+        let last = prediction.path.last().unwrap();
+        assert!((last.x - 62.0).abs() < 1e-3, "{}", last.x);
+        assert_eq!(last.y, 15.0);
+    }
+
+    #[test]
+    fn starting_inside_the_impact_radius_hits_now() {
+        let prediction = Prediction::new(&projectile(20.0, 15.0, (30.0, 0.0)), &BOUNDS, &Point::new(20.0, 16.0), &config()).unwrap();
+
+        assert_eq!(prediction.time_to_impact, Some(0.0));
+        assert_eq!(prediction.closest_time, 0.0);
+    }
+
+    #[test]
+    fn approaching_paths_report_the_time_to_impact() {
+        // 30 pixels per second toward a player 15 pixels away, entering the radius 12 pixels in
+        let prediction = Prediction::new(&projectile(5.0, 15.0, (30.0, 0.0)), &BOUNDS, &Point::new(20.0, 15.0), &config()).unwrap();
+
+        assert!((prediction.time_to_impact.unwrap() - 0.4).abs() < 1e-3);
+        assert!(prediction.closest_distance < 1e-3);
+        assert!((prediction.closest_time - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn passing_and_missing_paths_dont_hit() {
+        // tangent to a circle just outside the radius
+        let passing = Prediction::new(&projectile(5.0, 15.0, (30.0, 0.0)), &BOUNDS, &Point::new(20.0, 18.01), &config()).unwrap();
+
+        assert_eq!(passing.time_to_impact, None);
+        assert!((passing.closest_distance - 3.01).abs() < 1e-3);
+
+        // moves away
+        let away = Prediction::new(&projectile(15.0, 15.0, (-3.0, 0.0)), &BOUNDS, &Point::new(20.0, 15.0), &config()).unwrap();
+
+        assert_eq!(away.time_to_impact, None);
+        assert_eq!(away.closest_time, 0.0);
+
+        // stands still
+        let still = Prediction::new(&projectile(10.0, 15.0, (0.0, 0.0)), &BOUNDS, &Point::new(20.0, 15.0), &config()).unwrap();
+
+        assert_eq!(still.time_to_impact, None);
+        assert_eq!(still.closest_distance, 10.0);
+    }
+
+    #[test]
+    fn untracked_detections_have_no_prediction() {
+        let mut detection = projectile(5.0, 15.0, (30.0, 0.0));
+        detection.track_id = None;
+
+        assert!(Prediction::new(&detection, &BOUNDS, &far(), &config()).is_none());
+    }
+}
@@ -1,7 +1,7 @@
 use cgmath::{Matrix3, Vector2, Vector3};
 use wgpu::{Device, RenderPass, TextureFormat, util::DeviceExt};
 
-use crate::{img::Rectangle, rectangle_data::{RectangleInstance, RectangleVertex}};
+use crate::{coordinates::{Rectangle, WindowNormalized}, rectangle_data::{RectangleInstance, RectangleVertex}};
 
 
 pub struct RectangleRenderer {
@@ -95,7 +95,7 @@ impl RectangleRenderer {
         }
     }
 
-    fn add_rectangle(&mut self, permanent: bool, rectangle: &Rectangle<f32>, color: &[f32; 3]) {
+    fn add_rectangle(&mut self, permanent: bool, rectangle: &Rectangle<f32, WindowNormalized>, color: &[f32; 3]) {
         if self.permanent_count + self.transient_count >= self.max_instance_count {
             eprintln!("Attempting to add more rectangles than supported");
             return
@@ -130,11 +130,11 @@ impl RectangleRenderer {
         }
     }
 
-    pub fn add(&mut self, rectangle: &Rectangle<f32>, color: &[f32; 3]) {
+    pub fn add(&mut self, rectangle: &Rectangle<f32, WindowNormalized>, color: &[f32; 3]) {
         self.add_rectangle(false, rectangle, color);
     }
 
-    pub fn add_permanent(&mut self, rectangle: &Rectangle<f32>, color: &[f32; 3]) {
+    pub fn add_permanent(&mut self, rectangle: &Rectangle<f32, WindowNormalized>, color: &[f32; 3]) {
         self.add_rectangle(true, rectangle, color);
     }
 
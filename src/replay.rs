@@ -0,0 +1,86 @@
+use std::fmt::Display;
+
+use crate::{capture::Frame, config::{Config, ConfigError}, detector::Detection, input::RecordingInput, robot::Robot, session::SessionReader};
+
+
+// Feeds a recorded session through the robot using the recorded capture times
+// and prints every frame whose detections or mouse commands differ from the recording
+// sessions from before detections were recorded only compare bounding boxes
+// and sessions from before commands were recorded don't compare commands
+// the robot starts from the recorded background, version 1 sessions use the saved background if there is one
+// returns the number of differing frames
+pub fn replay(session: &SessionReader, config: Config) -> Result<usize, ConfigError> {
+    let mut robot = match &session.background {
+        Some(background) => Robot::with_session_background(RecordingInput::new(), config, background.clone())?,
+        None => {
+            eprintln!("Version 1 sessions don't record their background");
+            Robot::new(RecordingInput::new(), config, None)?
+        },
+    };
+
+    let mut differing = 0;
+
+    for i in 0..session.len() {
+        let recorded = session.frame(i).expect("replay: frame out of range");
+
+        let img = match session.image(i) {
+            Some(img) => img,
+            None => {
+                println!("frame {}: missing image", i);
+                differing += 1;
+                continue
+            }
+        };
+
+        let result = robot.process_frame(Frame {
+            img,
+            active_area: recorded.active_area.clone(),
+            t: recorded.t,
+        });
+
+        let mut lines = if session.version() >= 3 {
+            // compared as recorded, rounded by Detection's Display
+            let format = |detections: &[Detection]| detections.iter().map(|d| d.to_string()).collect::<Vec<_>>();
+
+            diff("detection", &format(&recorded.detections), &format(&result.detections))
+        } else {
+            let recorded = recorded.detections.iter().map(|d| d.bbox.clone()).collect::<Vec<_>>();
+
+            diff("rectangle", &recorded, &result.rectangles())
+        };
+        if session.version() >= 2 {
+            lines.extend(diff("command", &recorded.commands, robot.commands()));
+        }
+
+        // robot.commands() has this frame's commands
+        robot.input_mut().drain();
+
+        if !lines.is_empty() {
+            differing += 1;
+
+            println!("frame {} (t = {:.3}s)", i, recorded.t.as_secs_f32());
+            for line in lines {
+                println!("  {}", line);
+            }
+        }
+    }
+    println!("{} of {} frames differ", differing, session.len());
+
+    Ok(differing)
+}
+
+// positional diff, - recorded + replayed
+fn diff<T: PartialEq + Display>(name: &str, recorded: &[T], replayed: &[T]) -> Vec<String> {
+    let mut lines = vec![];
+
+    for i in 0..recorded.len().max(replayed.len()) {
+        match (recorded.get(i), replayed.get(i)) {
+            (Some(a), Some(b)) if a == b => {},
+            (a, b) => {
+                if let Some(a) = a { lines.push(format!("- {} {}: {}", name, i, a)) }
+                if let Some(b) = b { lines.push(format!("+ {} {}: {}", name, i, b)) }
+            },
+        }
+    }
+    lines
+}
@@ -1,147 +1,240 @@
-use crate::{background, capture::active_pixel_to_screen, capture_windows::{mouse_move, mouse_release}, img::{self, IMAGE_DOWNSCALE_FACTOR}, img::Rectangle, img_connected_components::{connected_components}, kalman::KalmanFilter};
+use std::time::{Duration, Instant};
 
+use crate::{background::{self, Background}, background_adaptive::{AdaptiveBackground, AdaptiveConfig}, capture::{Frame, active_pixel_to_screen}, config::{Config, ConfigError}, coordinates::Rectangle, detector::{self, DetectionClass, Detector, FrameContext}, frame_result::FrameResult, img::{self, IMAGE_DOWNSCALE_FACTOR, Image}, input::{InputSink, MouseCommand}, prediction::Prediction, session::SessionBackground, tracker::Tracker};
 
-pub struct Robot {
-    background: Vec<u8>,
-    head_tracker: HeadTracker,
+
+enum BackgroundModel {
+    Fixed(Background),
+    Adaptive(AdaptiveBackground),
+}
+
+pub struct Robot<I: InputSink> {
+    config: Config,
+    background: BackgroundModel,
+    // run in order on every frame
+    detectors: Vec<Box<dyn Detector>>,
+    // gives projectiles ids and velocities
+    tracker: Tracker,
+    input: I,
+    // where a learned background is saved after its warmup
+    persist_background: Option<String>,
+
+    // commands issued while processing the last frame
+    commands: Vec<MouseCommand>,
+    // capture time of the last frame
+    t: Duration,
 }
 
-impl Drop for Robot {
+impl<I: InputSink> Drop for Robot<I> {
     fn drop(&mut self) {
-        mouse_release();
+        self.command(MouseCommand::Release);
     }
 }
 
-impl Robot {
-    pub fn new() -> Option<Robot> {
-        let background = background::fetch_background()?;
+impl<I: InputSink> Robot<I> {
+    // learns the background while running if none was saved
+    // and saves what it learned to persist_background when given
+    pub fn new(input: I, config: Config, persist_background: Option<&str>) -> Result<Robot<I>, ConfigError> {
+        config.validate()?;
+
+        let persist_background = persist_background.map(str::to_string);
+
+        let background = match background::fetch_background() {
+            Some(background) => BackgroundModel::Fixed(background),
+            None => learn_background(&config.background.learned, &persist_background),
+        };
+
+        Robot::with_model(input, config, background, persist_background)
+    }
+
+    // subtracts a known background, one learned instead is never saved
+    pub fn with_background(input: I, config: Config, background: Background) -> Result<Robot<I>, ConfigError> {
+        config.validate()?;
+
+        Robot::with_model(input, config, BackgroundModel::Fixed(background), None)
+    }
+
+    // starts from a recorded session's background, a learned one is learned again with the recorded settings and never saved
+    pub fn with_session_background(input: I, mut config: Config, background: SessionBackground) -> Result<Robot<I>, ConfigError> {
+        let background = match background {
+            SessionBackground::Fixed(background) => BackgroundModel::Fixed(background),
+            SessionBackground::Learned(learned) => {
+                config.background.learned = learned;
+                learn_background(&config.background.learned, &None)
+            },
+        };
+
+        config.validate()?;
 
-        let head_tracker = HeadTracker::new();
+        Robot::with_model(input, config, background, None)
+    }
+
+    fn with_model(input: I, config: Config, background: BackgroundModel, persist_background: Option<String>) -> Result<Robot<I>, ConfigError> {
+        let detectors = detector::build_detectors(&config, vec![]);
+        let tracker = Tracker::new(&config.projectile_tracker);
 
-        Some( Robot {
+        Ok( Robot {
+            config,
             background,
-            head_tracker,
+            detectors,
+            tracker,
+            input,
+            persist_background,
+            commands: vec![],
+            t: Duration::ZERO,
         })
     }
 
-    pub fn process_frame(&mut self, img: Vec<u8>, active_area: &Rectangle<usize>) -> Vec<Rectangle<usize>> {
-        let (width, height, img) = img::shrink(img, active_area.width, active_area.height, IMAGE_DOWNSCALE_FACTOR);
+    // the background for a session recorded from the next frame on
+    // a background that started learning can only be recorded before its first frame
+    pub fn session_background(&self) -> SessionBackground {
+        match &self.background {
+            BackgroundModel::Fixed(background) => SessionBackground::Fixed(background.clone()),
+            BackgroundModel::Adaptive(background) => SessionBackground::Learned(background.config().clone()),
+        }
+    }
 
-        let img = img::remove_background(&img, &self.background);
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
 
-        self.head_tracker.update(&img, width, height);
+    // takes effect from the next frame, keeps the current config if the new one is invalid
+    // returns the changes
+    pub fn set_config(&mut self, config: Config) -> Result<Vec<String>, ConfigError> {
+        config.validate()?;
 
-        let h_other  = self.head_tracker.bound.clone();
-        let h_target = mirror_horizontal(&h_other, width); // target the non-blue head
+        let changes = self.config.changes(&config);
 
-        let fireball_rects = fireballs(&img, width, height);
-        let crystal_rects = crystals(&img, width, height);
+        let previous = std::mem::take(&mut self.detectors);
 
-        // response
+        self.detectors = detector::build_detectors(&config, previous);
 
-        let (x, y) = centroid(&active_pixel_to_screen(&h_target, active_area));
+        // a learned background starts over with new settings
+        if let BackgroundModel::Adaptive(background) = &self.background {
+            if background.config() != &config.background.learned {
+                self.background = learn_background(&config.background.learned, &self.persist_background);
+            }
+        }
 
-        // FIXME: default values not in window
-        // Causes loss of focus if detection fails on first frame
-        mouse_move(x, y);
+        self.tracker.reconfigure(&config.projectile_tracker);
+        self.config = config;
 
-        let mut r = vec![h_target, h_other];
+        Ok(changes)
+    }
 
-        r.extend(fireball_rects);
-        r.extend(crystal_rects);
+    pub fn input(&self) -> &I {
+        &self.input
+    }
 
-        r
+    pub fn input_mut(&mut self) -> &mut I {
+        &mut self.input
     }
-}
 
-fn crystals(img: &[u8], width: usize, height: usize) -> Vec<Rectangle<usize>> {
-    const RGB_THRESHOLDS: [(u8, u8); 3] = [(10,255), (30,204), (84,255)];
+    // sent while processing the last frame
+    pub fn commands(&self) -> &[MouseCommand] {
+        &self.commands
+    }
 
-    projectiles(img, width, height, &RGB_THRESHOLDS)
-}
+    fn command(&mut self, command: MouseCommand) {
+        self.input.send(self.t, &command);
+        self.commands.push(command);
+    }
 
-fn fireballs(img: &[u8], width: usize, height: usize) -> Vec<Rectangle<usize>> {
-    const RGB_THRESHOLDS: [(u8, u8); 3] = [(160,255), (14,250), (1,255)];
+    pub fn process_frame(&mut self, frame: Frame) -> FrameResult {
+        self.commands.clear();
 
-    projectiles(img, width, height, &RGB_THRESHOLDS)
-}
+        let Frame { img, active_area, t } = frame;
 
-fn projectiles(img: &[u8], width: usize, height: usize, rgb_thresholds: &[(u8, u8); 3]) -> Vec<Rectangle<usize>> {
-    const MIN_AREA: usize = 28;
-    const MAX_AREA: usize = 62;
-    const ROUNDNESS: f32 = 0.25;
+        self.t = t;
 
-    let img = img::threshold(img, rgb_thresholds);
+        let mut result = FrameResult::new(t);
+        let start = Instant::now();
 
-    let mut img = img::median3x3(&img, width, height);
+        let img = img::shrink(img, IMAGE_DOWNSCALE_FACTOR);
 
-    connected_components(&mut img, width, height)
-        .into_iter()
-        .filter(|c|{
-            let width = c.right - c.left;
-            let height = c.bottom - c.top;
-            let ratio = width as f32 / height as f32;
+        let start = result.time("shrink", start);
 
-            c.area > MIN_AREA &&
-            c.area < MAX_AREA &&
-            ratio > (1.0 - ROUNDNESS) &&
-            ratio < (1.0 + ROUNDNESS)
-        })
-        .map(|c| c.bounding_box())
-        .collect()
-}
+        let removed = match &mut self.background {
+            BackgroundModel::Fixed(background) => background.remove(&img, self.config.background.subtraction),
+            BackgroundModel::Adaptive(background) => {
+                // nothing is foreground while warming up
+                let mask = background.update(&img)
+                    .unwrap_or_else(|| Image::new(img.width(), img.height()));
 
-fn centroid(r: &Rectangle<usize>) -> (usize, usize) {
-    ( r.left + r.width / 2
-    , r.top + r.height / 2 )
-}
+                img::apply_mask(&img, &mask).map(|img| (img, mask))
+            },
+        };
 
-fn mirror_horizontal(r: &Rectangle<usize>, width: usize) -> Rectangle<usize> {
-    Rectangle { left: width - r.left - r.width, .. *r }
-}
+        // a background that doesn't fit the frames is learned again from the next frame on
+        let (img, mask) = removed.unwrap_or_else(|| {
+            eprintln!("Background size doesn't match the frame");
+            self.background = learn_background(&self.config.background.learned, &self.persist_background);
 
-struct HeadTracker {
-    pub bound: Rectangle<usize>,
-    pub filter: KalmanFilter,
-}
+            (Image::new(img.width(), img.height()), Image::new(img.width(), img.height()))
+        });
 
-impl HeadTracker {
-    fn update(&mut self, img: &[u8], width: usize, height: usize) {
-        const BBOX_WIDTH: usize = 17;
-        const BBOX_HEIGHT: usize = 25;
-        const BLUE_THRESHOLD_LOWER: u8 = 57;
-        const BLUE_THRESHOLD_UPPER: u8 = 203;
+        let mut start = result.time("background", start);
 
-        let img = img::threshold_blue(img, BLUE_THRESHOLD_LOWER, BLUE_THRESHOLD_UPPER);
+        let context = FrameContext { img: &img, foreground: &mask, t };
 
-        let mut img = img::median3x3(&img, width, height);
+        for detector in &mut self.detectors {
+            result.detections.extend(detector.detect(&context));
 
-        connected_components(&mut img, width, height)
-            .into_iter()
-            .max_by(|a, b|{ a.area.cmp(&b.area) })
-            .map(|c| self.bound = c.bounding_box());
+            start = result.time(detector.name(), start);
+        }
 
-        self.filter.predict();
+        result.detections = self.tracker.update(std::mem::take(&mut result.detections), t);
 
-        let (x, y) = centroid(&self.bound);
+        let start = result.time("track", start);
 
-        self.filter.update(&(x as f32, y as f32));
+        // without player detection, or while the player is only predicted, the player follows the cursor to the target head
+        let player = result.of_class(&DetectionClass::Player)
+            .filter(|d| d.confidence > 0.0)
+            .chain(result.of_class(&DetectionClass::TargetHead))
+            .next()
+            .map(|d| d.centroid);
 
-        let p = self.filter.position();
+        if let Some(player) = player {
+            let bounds = Rectangle::new(0, 0, img.width(), img.height());
 
-        self.bound = Rectangle {
-            left: p.0 as usize - BBOX_WIDTH / 2,
-            top: p.1 as usize - BBOX_HEIGHT / 2,
-            width: BBOX_WIDTH,
-            height: BBOX_HEIGHT,
-        };
-    }
+            result.predictions = result.detections.iter()
+                .filter_map(|d| Prediction::new(d, &bounds, &player, &self.config.prediction))
+                .collect();
+        }
+
+        let start = result.time("predict", start);
 
-    // default instead ?
-    fn new() -> Self {
-        HeadTracker {
-            filter: KalmanFilter::default(),
-            bound: Rectangle { left: 0, top: 0, width: 0, height: 0 },
+        // response
+
+        // FIXME: default values not in window
+        // Causes loss of focus if detection fails on first frame
+        let target = result.of_class(&DetectionClass::TargetHead)
+            .next()
+            .and_then(|d| active_pixel_to_screen(&d.bbox, &active_area));
+
+        if let Some(target) = target {
+            let p = target.center();
+
+            let action = MouseCommand::Move { x: p.x, y: p.y };
+
+            self.command(action.clone());
+            result.action = Some(action);
         }
+
+        // FIXME: workaround for the overlay taking focus on input
+        self.command(MouseCommand::Press);
+
+        result.time("response", start);
+
+        result
+    }
+}
+
+fn learn_background(config: &AdaptiveConfig, persist: &Option<String>) -> BackgroundModel {
+    match persist {
+        Some(filename) => eprintln!("Learning the background over the first {} frames, saving it to {}", config.warmup_frames, filename),
+        None => eprintln!("Learning the background over the first {} frames", config.warmup_frames),
     }
+
+    BackgroundModel::Adaptive(AdaptiveBackground::new(config.clone(), persist.clone()))
 }
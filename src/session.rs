@@ -0,0 +1,545 @@
+use std::{fs::File, io::{self, BufRead, BufReader, BufWriter, Write}, path::{Path, PathBuf}, time::Duration};
+
+use crate::{background::Background, background_adaptive::{AdaptiveConfig, MixtureParameters}, capture::{Frame, FrameSource}, coordinates::{Rectangle, Screen}, detector::{Detection, DetectionClass}, img::{self, Image, Rgb8}, input::MouseCommand};
+
+
+// On-disk layout of a recorded session
+// - <directory>/index.txt
+//      bumper_robot_session <version>
+//      window <left> <top> <width> <height>
+//      background fixed
+//          | background learned <warmup frames> <tolerance r g b> none
+//          | background learned <warmup frames> <tolerance r g b> <components> <learning rate> <initial variance> <min variance> <match deviations> <background weight>
+//      one line per frame:
+//      <frame> <t in microseconds> <active area> <detection count> <detections...> <command count> <commands...>
+//      (each detection as in Detection's Display, each command as in MouseCommand's Display)
+// - <directory>/frame<i>.png holds the active area
+// - a fixed background is in <directory>/background.png, its per pixel and channel mean then variance
+//   as little-endian f32 in <directory>/background_statistics.bin and its flicker mask, if any, in <directory>/background_flicker.png
+// - a learned background is learned again from the first frames
+// versions 1 and 2 record untyped rectangles (<left> <top> <width> <height>) instead of detections,
+// read as the target head, the other head, then projectiles of unknown kind
+// version 1 sessions have no commands and no background
+pub const SESSION_VERSION: u32 = 3;
+
+// projectile name of detections read from rectangles
+pub const LEGACY_PROJECTILE: &str = "unknown";
+
+const SESSION_MAGIC: &str = "bumper_robot_session";
+const INDEX_FILENAME: &str = "index.txt";
+const BACKGROUND_FILENAME: &str = "background.png";
+const STATISTICS_FILENAME: &str = "background_statistics.bin";
+const FLICKER_FILENAME: &str = "background_flicker.png";
+
+fn frame_filename(i: usize) -> String {
+    format!("frame{}.png", i)
+}
+
+// The background the robot started the session from
+#[derive(Clone)]
+pub enum SessionBackground {
+    // saved or estimated before the session
+    Fixed(Background),
+    // learned over the session's first frames with these settings
+    Learned(AdaptiveConfig),
+}
+
+#[derive(Debug, Clone)]
+pub struct SessionFrame {
+    // capture time since the start of the session
+    pub t: Duration,
+    pub active_area: Rectangle<usize, Screen>,
+    // FrameResult::detections
+    pub detections: Vec<Detection>,
+    pub commands: Vec<MouseCommand>,
+}
+
+pub struct SessionWriter {
+    directory: PathBuf,
+    index: BufWriter<File>,
+    frame_count: usize,
+}
+
+impl SessionWriter {
+    // the background has to be the one the robot processes the first recorded frame with
+    pub fn create(directory: &str, window: &Rectangle<usize, Screen>, background: &SessionBackground) -> io::Result<Self> {
+        let directory = PathBuf::from(directory);
+
+        std::fs::create_dir_all(&directory)?;
+
+        let mut index = BufWriter::new(File::create(directory.join(INDEX_FILENAME))?);
+
+        writeln!(index, "{} {}", SESSION_MAGIC, SESSION_VERSION)?;
+        writeln!(index, "window {}", window)?;
+
+        match background {
+            SessionBackground::Fixed(background) => {
+                writeln!(index, "background fixed")?;
+
+                save(&background.image, &directory.join(BACKGROUND_FILENAME))?;
+
+                let statistics = background.mean.iter().chain(&background.variance)
+                    .flat_map(|v| v.to_le_bytes())
+                    .collect::<Vec<_>>();
+
+                std::fs::write(directory.join(STATISTICS_FILENAME), statistics)?;
+
+                if let Some(flicker) = &background.flicker {
+                    save(flicker, &directory.join(FLICKER_FILENAME))?;
+                }
+            },
+            SessionBackground::Learned(config) => {
+                let [r, g, b] = config.tolerance;
+
+                write!(index, "background learned {} {} {} {}", config.warmup_frames, r, g, b)?;
+
+                match &config.mixture {
+                    Some(m) => writeln!(index, " {} {} {} {} {} {}",
+                        m.components, m.learning_rate, m.initial_variance, m.min_variance, m.match_deviations, m.background_weight)?,
+                    None => writeln!(index, " none")?,
+                }
+            },
+        }
+
+        Ok(SessionWriter { directory, index, frame_count: 0 })
+    }
+
+    pub fn write_frame(&mut self, img: &Image<Rgb8>, frame: &SessionFrame) -> io::Result<()> {
+        let i = self.frame_count;
+
+        save(img, &self.directory.join(frame_filename(i)))?;
+
+        write!(self.index, "{} {} {} {}",
+            i,
+            frame.t.as_micros(),
+            frame.active_area,
+            frame.detections.len())?;
+
+        for d in &frame.detections {
+            write!(self.index, " {}", d)?;
+        }
+
+        write!(self.index, " {}", frame.commands.len())?;
+
+        for c in &frame.commands {
+            write!(self.index, " {}", c)?;
+        }
+        writeln!(self.index)?;
+
+        // keep the index readable if the session is interrupted
+        self.index.flush()?;
+
+        self.frame_count += 1;
+
+        Ok(())
+    }
+}
+
+pub struct SessionReader {
+    directory: PathBuf,
+    frames: Vec<SessionFrame>,
+    next: usize,
+    version: u32,
+
+    pub window: Rectangle<usize, Screen>,
+    // None for version 1 sessions
+    pub background: Option<SessionBackground>,
+}
+
+impl SessionReader {
+    pub fn open(directory: &str) -> io::Result<Self> {
+        let directory = PathBuf::from(directory);
+
+        let index = BufReader::new(File::open(directory.join(INDEX_FILENAME))?);
+        let mut lines = index.lines();
+
+        let header = lines.next().transpose()?.unwrap_or_default();
+
+        let version = match header.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [SESSION_MAGIC, version] => match version.parse::<u32>() {
+                Ok(version @ 1..=SESSION_VERSION) => version,
+                _ => return Err(invalid_data(format!("unsupported session version {}", version))),
+            },
+            _ => return Err(invalid_data(format!("{} is not a session index", INDEX_FILENAME))),
+        };
+
+        let window = lines.next().transpose()?
+            .and_then(|line| line.strip_prefix("window ").and_then(|s| s.parse().ok()))
+            .ok_or_else(|| invalid_data("missing window rectangle".to_string()))?;
+
+        let background = if version >= 2 {
+            let line = lines.next().transpose()?.unwrap_or_default();
+
+            Some(read_background(&directory, &line)?)
+        } else {
+            None
+        };
+
+        // the frames start after the header lines
+        let first_line = if version >= 2 { 4 } else { 3 };
+
+        let mut frames = vec![];
+
+        for (line_number, line) in lines.enumerate() {
+            let line = line?;
+
+            let frame = parse_frame(&line, frames.len(), version)
+                .ok_or_else(|| invalid_data(format!("malformed frame entry on line {}", line_number + first_line)))?;
+
+            frames.push(frame);
+        }
+
+        Ok(SessionReader { directory, frames, next: 0, version, window, background })
+    }
+
+    // format version the session was recorded with
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn frame(&self, i: usize) -> Option<&SessionFrame> {
+        self.frames.get(i)
+    }
+
+    // loads the active area of frame i
+    pub fn image(&self, i: usize) -> Option<Image<Rgb8>> {
+        img::load(self.directory.join(frame_filename(i)).to_str()?).map(img::bgr_to_rgb)
+    }
+
+    // position of the next frame returned by try_frame
+    pub fn seek(&mut self, i: usize) {
+        self.next = i.min(self.frames.len());
+    }
+
+    pub fn position(&self) -> usize {
+        self.next
+    }
+}
+
+impl FrameSource for SessionReader {
+    // frames keep their recorded capture time
+    fn try_frame(&mut self) -> Option<Frame> {
+        let SessionFrame { active_area, t, .. } = self.frames.get(self.next)?.clone();
+        let img = self.image(self.next)?;
+
+        self.next += 1;
+
+        Some(Frame { img, active_area, t })
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn save<P: img::ColorFormat>(img: &Image<P>, filename: &Path) -> io::Result<()> {
+    let filename = filename.to_str().ok_or_else(|| invalid_data(format!("{} is not valid unicode", filename.display())))?;
+
+    img.try_save(filename).map_err(io::Error::other)
+}
+
+fn read_background(directory: &Path, line: &str) -> io::Result<SessionBackground> {
+    let malformed = || invalid_data(format!("malformed background entry {}", line));
+
+    let mut values = line.split_whitespace();
+
+    if values.next() != Some("background") { return Err(malformed()) }
+
+    match values.next() {
+        Some("fixed") => {
+            let load = |filename| directory.join(filename).to_str().and_then(img::load).map(img::bgr_to_rgb);
+            let image = load(BACKGROUND_FILENAME).ok_or_else(|| invalid_data(format!("missing {}", BACKGROUND_FILENAME)))?;
+
+            let statistics = std::fs::read(directory.join(STATISTICS_FILENAME))?
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect::<Vec<_>>();
+
+            if statistics.len() != 2 * image.data().len() {
+                return Err(invalid_data(format!("{} doesn't match {}", STATISTICS_FILENAME, BACKGROUND_FILENAME)));
+            }
+
+            let (mean, variance) = statistics.split_at(image.data().len());
+
+            let flicker = match directory.join(FLICKER_FILENAME).to_str().and_then(img::load_luma) {
+                Some(flicker) if flicker.width() != image.width() || flicker.height() != image.height() => {
+                    return Err(invalid_data(format!("{} doesn't match {}", FLICKER_FILENAME, BACKGROUND_FILENAME)))
+                },
+                flicker => flicker,
+            };
+
+            Ok(SessionBackground::Fixed(Background { image, mean: mean.to_vec(), variance: variance.to_vec(), flicker }))
+        },
+        Some("learned") => parse_learned(&mut values)
+            .filter(|_| values.next().is_none())
+            .map(SessionBackground::Learned)
+            .ok_or_else(malformed),
+        _ => Err(malformed()),
+    }
+}
+
+fn parse_learned<'a>(values: &mut impl Iterator<Item = &'a str>) -> Option<AdaptiveConfig> {
+    let warmup_frames = values.next()?.parse().ok()?;
+    let tolerance = [values.next()?.parse().ok()?, values.next()?.parse().ok()?, values.next()?.parse().ok()?];
+
+    let mixture = match values.next()? {
+        "none" => None,
+        components => Some(MixtureParameters {
+            components: components.parse().ok()?,
+            learning_rate: values.next()?.parse().ok()?,
+            initial_variance: values.next()?.parse().ok()?,
+            min_variance: values.next()?.parse().ok()?,
+            match_deviations: values.next()?.parse().ok()?,
+            background_weight: values.next()?.parse().ok()?,
+        }),
+    };
+
+    Some(AdaptiveConfig { warmup_frames, tolerance, mixture })
+}
+
+fn next_rectangle<'a, S>(values: &mut impl Iterator<Item = &'a str>) -> Option<Rectangle<usize, S>> {
+    Some(Rectangle::new(
+        values.next()?.parse().ok()?,
+        values.next()?.parse().ok()?,
+        values.next()?.parse().ok()?,
+        values.next()?.parse().ok()?,
+    ))
+}
+
+fn next_command<'a>(values: &mut impl Iterator<Item = &'a str>) -> Option<MouseCommand> {
+    match values.next()? {
+        "move" => Some(MouseCommand::Move {
+            x: values.next()?.parse().ok()?,
+            y: values.next()?.parse().ok()?,
+        }),
+        name => name.parse().ok(),
+    }
+}
+
+// a detection is always 11 values
+fn next_detection<'a>(values: &mut impl Iterator<Item = &'a str>) -> Option<Detection> {
+    values.take(11).collect::<Vec<_>>().join(" ").parse().ok()
+}
+
+// what the rectangle at index i of a version 1 or 2 frame was
+fn legacy_class(i: usize) -> DetectionClass {
+    match i {
+        0 => DetectionClass::TargetHead,
+        1 => DetectionClass::OtherHead,
+        _ => DetectionClass::Projectile(LEGACY_PROJECTILE.to_string()),
+    }
+}
+
+fn parse_frame(line: &str, expected_index: usize, version: u32) -> Option<SessionFrame> {
+    let mut values = line.split_whitespace();
+
+    let i: usize = values.next()?.parse().ok()?;
+    if i != expected_index { return None }
+
+    let t = Duration::from_micros(values.next()?.parse().ok()?);
+
+    let active_area = next_rectangle(&mut values)?;
+
+    let count: usize = values.next()?.parse().ok()?;
+
+    let detections = if version >= 3 {
+        (0..count)
+            .map(|_| next_detection(&mut values))
+            .collect::<Option<Vec<_>>>()?
+    } else {
+        (0..count)
+            .map(|i| Some(Detection::new(legacy_class(i), next_rectangle(&mut values)?, 1.0)))
+            .collect::<Option<Vec<_>>>()?
+    };
+
+    let commands = if version >= 2 {
+        let count: usize = values.next()?.parse().ok()?;
+
+        (0..count)
+            .map(|_| next_command(&mut values))
+            .collect::<Option<Vec<_>>>()?
+    } else {
+        vec![]
+    };
+
+    if values.next().is_some() { return None }
+
+    Some(SessionFrame { t, active_area, detections, commands })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("bumper_robot_session_{}_{}", name, std::process::id()));
+
+        let _ = std::fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn write_index(directory: &PathBuf, lines: &[&str]) {
+        std::fs::create_dir_all(directory).unwrap();
+        std::fs::write(directory.join(INDEX_FILENAME), lines.join("\n")).unwrap();
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let directory = directory("round_trip");
+
+        let mut projectile = Detection::new(DetectionClass::Projectile("fireball".to_string()), Rectangle::new(3, 4, 2, 2), 0.5);
+        projectile.track_id = Some(7);
+        projectile.velocity = Some((-1.5, 2.25));
+
+        let frame = SessionFrame {
+            t: Duration::from_micros(1_234_567),
+            active_area: Rectangle::new(100, 50, 320, 240),
+            detections: vec![
+                Detection::new(DetectionClass::TargetHead, Rectangle::new(20, 1, 17, 25), 1.0),
+                projectile,
+            ],
+            commands: vec![MouseCommand::Move { x: 300, y: 150 }, MouseCommand::Press],
+        };
+
+        let background = SessionBackground::Learned(AdaptiveConfig::default());
+
+        let mut writer = SessionWriter::create(directory.to_str().unwrap(), &Rectangle::new(0, 0, 640, 480), &background).unwrap();
+        writer.write_frame(&Image::new(4, 3), &frame).unwrap();
+        drop(writer);
+
+        let reader = SessionReader::open(directory.to_str().unwrap()).unwrap();
+        let read = reader.frame(0).unwrap();
+
+        assert_eq!(reader.version(), SESSION_VERSION);
+        assert_eq!(reader.len(), 1);
+        assert_eq!(reader.window, Rectangle::new(0, 0, 640, 480));
+        assert_eq!(read.t, frame.t);
+        assert_eq!(read.active_area, frame.active_area);
+        assert_eq!(read.detections, frame.detections);
+        assert_eq!(read.commands, frame.commands);
+        assert_eq!(reader.image(0).unwrap().width(), 4);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    fn read_background(name: &str, background: &SessionBackground) -> SessionBackground {
+        let directory = directory(name);
+
+        drop(SessionWriter::create(directory.to_str().unwrap(), &Rectangle::new(0, 0, 640, 480), background).unwrap());
+
+        let reader = SessionReader::open(directory.to_str().unwrap()).unwrap();
+        std::fs::remove_dir_all(directory).unwrap();
+
+        reader.background.unwrap()
+    }
+
+    #[test]
+    fn fixed_backgrounds_round_trip() {
+        let image: Image<Rgb8> = Image::from_raw(2, 1, vec![1, 2, 3, 4, 5, 6]).unwrap();
+
+        let mut background = Background::from_samples(image.clone(), &[image.clone(), Image::new(2, 1)]).unwrap();
+        background.flicker = Some(Image::from_raw(2, 1, vec![0, 255]).unwrap());
+
+        match read_background("fixed", &SessionBackground::Fixed(background.clone())) {
+            SessionBackground::Fixed(read) => {
+                assert_eq!(read.image, background.image);
+                assert_eq!(read.mean, background.mean);
+                assert_eq!(read.variance, background.variance);
+                assert_eq!(read.flicker, background.flicker);
+            },
+            SessionBackground::Learned(_) => panic!("learned instead of fixed"),
+        }
+
+        // without a flicker mask
+        match read_background("fixed_without_flicker", &SessionBackground::Fixed(Background::from_image(image))) {
+            SessionBackground::Fixed(read) => assert!(read.flicker.is_none()),
+            SessionBackground::Learned(_) => panic!("learned instead of fixed"),
+        }
+    }
+
+    #[test]
+    fn learned_settings_round_trip() {
+        for mixture in [None, Some(MixtureParameters { learning_rate: 0.125, ..MixtureParameters::default() })] {
+            let config = AdaptiveConfig { warmup_frames: 7, tolerance: [1, 2, 3], mixture };
+
+            match read_background("learned", &SessionBackground::Learned(config.clone())) {
+                SessionBackground::Learned(read) => assert_eq!(read, config),
+                SessionBackground::Fixed(_) => panic!("fixed instead of learned"),
+            }
+        }
+    }
+
+    #[test]
+    fn legacy_rectangles_are_read_in_order() {
+        let directory = directory("legacy");
+
+        write_index(&directory, &[
+            "bumper_robot_session 2",
+            "window 0 0 640 480",
+            "background learned 60 8 8 8 none",
+            "0 1000 100 50 320 240 3 20 1 17 25 2 1 17 25 5 5 2 2 1 press",
+        ]);
+
+        let reader = SessionReader::open(directory.to_str().unwrap()).unwrap();
+        let classes = reader.frame(0).unwrap().detections.iter().map(|d| d.class.clone()).collect::<Vec<_>>();
+
+        assert_eq!(reader.version(), 2);
+        assert_eq!(classes, vec![
+            DetectionClass::TargetHead,
+            DetectionClass::OtherHead,
+            DetectionClass::Projectile(LEGACY_PROJECTILE.to_string()),
+        ]);
+        assert_eq!(reader.frame(0).unwrap().commands, vec![MouseCommand::Press]);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn version_1_has_no_commands() {
+        let directory = directory("version_1");
+
+        write_index(&directory, &[
+            "bumper_robot_session 1",
+            "window 0 0 640 480",
+            "0 1000 100 50 320 240 1 20 1 17 25",
+        ]);
+
+        let reader = SessionReader::open(directory.to_str().unwrap()).unwrap();
+
+        assert!(reader.frame(0).unwrap().commands.is_empty());
+        assert_eq!(reader.frame(0).unwrap().detections.len(), 1);
+        assert!(reader.background.is_none());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn malformed_indices_are_rejected() {
+        for (name, lines) in [
+            ("future", &["bumper_robot_session 99", "window 0 0 640 480"][..]),
+            ("not_a_session", &["something else", "window 0 0 640 480"][..]),
+            ("skipped_frame", &["bumper_robot_session 3", "window 0 0 640 480", "background learned 60 8 8 8 none", "1 0 0 0 1 1 0 0"][..]),
+            ("trailing", &["bumper_robot_session 3", "window 0 0 640 480", "background learned 60 8 8 8 none", "0 0 0 0 1 1 0 0 press"][..]),
+            ("no_background", &["bumper_robot_session 3", "window 0 0 640 480", "0 0 0 0 1 1 0 0"][..]),
+            ("missing_background", &["bumper_robot_session 3", "window 0 0 640 480", "background fixed"][..]),
+            ("short_mixture", &["bumper_robot_session 3", "window 0 0 640 480", "background learned 60 8 8 8 3 0.01"][..]),
+        ] {
+            let directory = directory(name);
+            write_index(&directory, lines);
+
+            let error = SessionReader::open(directory.to_str().unwrap()).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", name);
+
+            std::fs::remove_dir_all(directory).unwrap();
+        }
+    }
+}
@@ -0,0 +1,368 @@
+use std::time::Duration;
+
+use crate::{config::ProjectileTrackerConfig, detector::{Detection, DetectionClass}, kalman::KalmanFilter};
+
+
+// One projectile followed across frames
+struct Track {
+    id: u32,
+    class: DetectionClass,
+    filter: KalmanFilter,
+    // consecutive frames with and without a detection
+    hits: usize,
+    misses: usize,
+    // stays confirmed until deleted
+    confirmed: bool,
+}
+
+// Follows projectiles with a KalmanFilter each
+// - detections are associated to the predicted tracks of their class by global nearest neighbour
+//   within the gate, minimizing the total squared Mahalanobis distance
+// - an unassociated detection starts a tentative track, which is confirmed after confirm_hits
+//   consecutive detections and deleted on its first miss
+// - a confirmed track is deleted after more than max_misses consecutive misses
+pub struct Tracker {
+    config: ProjectileTrackerConfig,
+    tracks: Vec<Track>,
+    next_id: u32,
+    // capture time of the last update
+    t_last: Option<Duration>,
+}
+
+impl Tracker {
+    pub fn new(config: &ProjectileTrackerConfig) -> Self {
+        Tracker { config: config.clone(), tracks: vec![], next_id: 0, t_last: None }
+    }
+
+    // keeps the current tracks
+    pub fn reconfigure(&mut self, config: &ProjectileTrackerConfig) {
+        for track in &mut self.tracks {
+            track.filter.set_noise(config.process_noise, config.measurement_noise);
+        }
+        self.config = config.clone();
+    }
+
+    // projectile detections get the id and velocity of their track once it is confirmed
+    // other detections pass through unchanged
+    pub fn update(&mut self, mut detections: Vec<Detection>, t: Duration) -> Vec<Detection> {
+        let config = &self.config;
+
+        let dt = self.t_last.map_or(Duration::ZERO, |t_last| t.saturating_sub(t_last));
+        self.t_last = Some(t);
+
+        for track in &mut self.tracks {
+            track.filter.predict(dt.as_secs_f32());
+        }
+
+        let projectiles = detections.iter()
+            .enumerate()
+            .filter(|(_, d)| matches!(d.class, DetectionClass::Projectile(_)))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+
+        let costs = self.tracks.iter()
+            .map(|track| projectiles.iter()
+                .map(|&i| {
+                    let d = &detections[i];
+                    let distance = track.filter.distance(&(d.centroid.x, d.centroid.y));
+
+                    (d.class == track.class && distance <= config.gate).then_some(distance)
+                })
+                .collect())
+            .collect::<Vec<_>>();
+
+        let assignment = assign(&costs, projectiles.len(), config.gate);
+
+        let mut associated = vec![false; projectiles.len()];
+
+        for (track, column) in self.tracks.iter_mut().zip(assignment) {
+            let column = match column {
+                Some(column) => column,
+                None => {
+                    track.hits = 0;
+                    track.misses += 1;
+                    continue
+                },
+            };
+            associated[column] = true;
+
+            let d = &mut detections[projectiles[column]];
+
+            track.filter.update(&(d.centroid.x, d.centroid.y));
+            track.hits += 1;
+            track.misses = 0;
+            track.confirmed |= track.hits >= config.confirm_hits;
+
+            if track.confirmed {
+                d.track_id = Some(track.id);
+                d.velocity = Some(track.filter.velocity());
+            }
+        }
+
+        self.tracks.retain(|track| {
+            if track.confirmed { track.misses <= config.max_misses } else { track.misses == 0 }
+        });
+
+        for (column, &i) in projectiles.iter().enumerate() {
+            if associated[column] { continue }
+
+            let d = &mut detections[i];
+
+            let track = Track {
+                id: self.next_id,
+                class: d.class.clone(),
+                filter: KalmanFilter::at((d.centroid.x, d.centroid.y), config.process_noise, config.measurement_noise),
+                hits: 1,
+                misses: 0,
+                confirmed: config.confirm_hits <= 1,
+            };
+            self.next_id += 1;
+
+            if track.confirmed {
+                d.track_id = Some(track.id);
+                d.velocity = Some(track.filter.velocity());
+            }
+            self.tracks.push(track);
+        }
+
+        detections
+    }
+}
+
+// Minimum cost assignment of rows to columns, None costs are outside the gate
+// leaving a row or a column unassigned costs `unassigned` each
+// returns the column of every row
+#[allow(clippy::needless_range_loop)]
+fn assign(costs: &[Vec<Option<f32>>], columns: usize, unassigned: f32) -> Vec<Option<usize>> {
+    let rows = costs.len();
+
+    // square with a dummy column per row and a dummy row per column
+    let n = rows + columns;
+
+    // never worth more than leaving both unassigned
+    let forbidden = 4.0 * unassigned as f64 + 1.0;
+
+    let cost = |i: usize, j: usize| match (i < rows, j < columns) {
+        (true, true) => costs[i][j].map_or(forbidden, |c| c as f64),
+        (false, false) => 0.0,
+        _ => unassigned as f64,
+    };
+
+    // Hungarian algorithm with row and column potentials u, v
+    // 1-indexed, column 0 is where each row's augmenting path starts
+    let mut u = vec![0.0; n + 1];
+    let mut v = vec![0.0; n + 1];
+    // row of each column, 0 for none
+    let mut row = vec![0; n + 1];
+    // previous column on the augmenting path
+    let mut way = vec![0; n + 1];
+
+    for i in 1..=n {
+        row[0] = i;
+
+        let mut j0 = 0;
+        let mut min = vec![f64::INFINITY; n + 1];
+        let mut used = vec![false; n + 1];
+
+        while row[j0] != 0 {
+            used[j0] = true;
+
+            let i0 = row[j0];
+            let mut delta = f64::INFINITY;
+            let mut j1 = 0;
+
+            for j in 1..=n {
+                if used[j] { continue }
+
+                let reduced = cost(i0 - 1, j - 1) - u[i0] - v[j];
+
+                if reduced < min[j] {
+                    min[j] = reduced;
+                    way[j] = j0;
+                }
+                if min[j] < delta {
+                    delta = min[j];
+                    j1 = j;
+                }
+            }
+
+            for j in 0..=n {
+                if used[j] {
+                    u[row[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min[j] -= delta;
+                }
+            }
+
+            j0 = j1;
+        }
+
+        // flip the augmenting path
+        while j0 != 0 {
+            let j1 = way[j0];
+            row[j0] = row[j1];
+            j0 = j1;
+        }
+    }
+
+    let mut assignment = vec![None; rows];
+
+    for j in 1..=columns {
+        let i = row[j];
+
+        if (1..=rows).contains(&i) && costs[i - 1][j - 1].is_some() {
+            assignment[i - 1] = Some(j - 1);
+        }
+    }
+    assignment
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coordinates::{Point, Rectangle};
+
+    const FRAME: Duration = Duration::from_millis(16);
+
+    fn projectile(name: &str, x: f32, y: f32) -> Detection {
+        Detection {
+            centroid: Point::new(x, y),
+            ..Detection::new(DetectionClass::Projectile(name.to_string()), Rectangle::new(x as usize, y as usize, 2, 2), 1.0)
+        }
+    }
+
+    fn ids(detections: &[Detection]) -> Vec<Option<u32>> {
+        detections.iter().map(|d| d.track_id).collect()
+    }
+
+    // updates on consecutive frames from frame, returns the last result
+    fn run(tracker: &mut Tracker, frame: &mut u32, frames: &[Vec<Detection>]) -> Vec<Detection> {
+        let mut last = vec![];
+
+        for detections in frames {
+            last = tracker.update(detections.clone(), FRAME * *frame);
+            *frame += 1;
+        }
+        last
+    }
+
+    #[test]
+    fn gated_pairs_are_left_unassigned() {
+        assert_eq!(assign(&[vec![None]], 1, 9.21), vec![None]);
+        assert_eq!(assign(&[vec![None, Some(3.0)], vec![None, None]], 2, 9.21), vec![Some(1), None]);
+        assert_eq!(assign(&[], 3, 9.21), vec![]);
+        assert_eq!(assign(&[vec![], vec![]], 0, 9.21), vec![None, None]);
+    }
+
+    #[test]
+    fn crossing_pairs_minimize_the_total_cost() {
+        // greedy would take 1.0 and be left with 8.0
+        let costs = [
+            vec![Some(1.0), Some(2.0)],
+            vec![Some(1.5), Some(8.0)],
+        ];
+
+        assert_eq!(assign(&costs, 2, 9.21), vec![Some(1), Some(0)]);
+
+        // the cheapest pair would leave the other row without a column inside its gate
+        let costs = [
+            vec![Some(1.0), Some(9.0)],
+            vec![Some(9.0), None],
+        ];
+
+        assert_eq!(assign(&costs, 2, 9.21), vec![Some(1), Some(0)]);
+    }
+
+    #[test]
+    fn tracks_are_confirmed_after_confirm_hits() {
+        let mut tracker = Tracker::new(&ProjectileTrackerConfig::default());
+        let mut frame = 0;
+
+        for expected in [None, None, Some(0)] {
+            let result = run(&mut tracker, &mut frame, &[vec![projectile("fireball", 20.0, 20.0)]]);
+
+            assert_eq!(ids(&result), vec![expected]);
+            assert_eq!(result[0].velocity.is_some(), expected.is_some());
+        }
+    }
+
+    #[test]
+    fn tentative_tracks_are_deleted_on_their_first_miss() {
+        let mut tracker = Tracker::new(&ProjectileTrackerConfig::default());
+        let mut frame = 0;
+
+        let seen = vec![projectile("fireball", 20.0, 20.0)];
+
+        run(&mut tracker, &mut frame, &[seen.clone(), seen.clone(), vec![]]);
+
+        // a new track, not the deleted one
+        let result = run(&mut tracker, &mut frame, &[seen.clone(), seen.clone(), seen.clone()]);
+
+        assert_eq!(ids(&result), vec![Some(1)]);
+    }
+
+    #[test]
+    fn confirmed_tracks_are_deleted_after_max_misses() {
+        let config = ProjectileTrackerConfig::default();
+        let seen = vec![projectile("fireball", 20.0, 20.0)];
+
+        for (misses, expected) in [(config.max_misses, Some(0)), (config.max_misses + 1, None)] {
+            let mut tracker = Tracker::new(&config);
+            let mut frame = 0;
+
+            run(&mut tracker, &mut frame, &[seen.clone(), seen.clone(), seen.clone()]);
+            run(&mut tracker, &mut frame, &vec![vec![]; misses]);
+
+            let result = run(&mut tracker, &mut frame, std::slice::from_ref(&seen));
+
+            assert_eq!(ids(&result), vec![expected], "{} misses", misses);
+        }
+    }
+
+    #[test]
+    fn ids_are_stable_across_frames() {
+        let mut tracker = Tracker::new(&ProjectileTrackerConfig::default());
+        let mut frame = 0;
+
+        let mut last = vec![];
+
+        // two projectiles moving toward each other, in changing detection order
+        for i in 0..20 {
+            let x = i as f32;
+            let mut detections = vec![projectile("fireball", 10.0 + x, 30.0), projectile("fireball", 90.0 - x, 10.0)];
+
+            if i % 2 == 1 { detections.reverse() }
+
+            let result = run(&mut tracker, &mut frame, &[detections]);
+
+            let mut by_position = result.iter()
+                .map(|d| (d.centroid.y as u32, d.track_id))
+                .collect::<Vec<_>>();
+            by_position.sort_unstable();
+
+            if i >= 2 {
+                assert_eq!(by_position, vec![(10, Some(1)), (30, Some(0))], "frame {}", i);
+            }
+            last = result;
+        }
+
+        let moving_right = last.iter().find(|d| d.track_id == Some(0)).unwrap();
+        assert!(moving_right.velocity.unwrap().0 > 0.0);
+    }
+
+    #[test]
+    fn classes_and_other_detections_are_kept_apart() {
+        let mut tracker = Tracker::new(&ProjectileTrackerConfig { confirm_hits: 1, ..ProjectileTrackerConfig::default() });
+        let mut frame = 0;
+
+        let head = Detection::new(DetectionClass::TargetHead, Rectangle::new(20, 20, 17, 25), 1.0);
+        let detections = vec![head.clone(), projectile("fireball", 20.0, 20.0), projectile("crystal", 20.0, 20.0)];
+
+        let result = run(&mut tracker, &mut frame, &[detections.clone(), detections]);
+
+        assert_eq!(result[0], head);
+        assert_eq!(ids(&result), vec![None, Some(0), Some(1)]);
+    }
+}